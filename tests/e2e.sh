@@ -122,10 +122,6 @@ sleep $(echo "$DURATION + 1" | bc)
 echo "Stopping recording..."
 ./target/release/vypec stop
 
-# Send a second stop to reduce chance of one-shot IPC loss under load
-sleep 1
-./target/release/vypec stop
-
 # Wait for stop + final transcription
 echo "Waiting for final transcription..."
 for _ in {1..60}; do
@@ -0,0 +1,72 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use vype_shared::{TypingState, edit_ops};
+
+const SENTENCE: &str = "The quick brown fox jumps over the lazy dog, then naïvely naps. ";
+
+fn transcript(len: usize) -> String {
+    SENTENCE.chars().cycle().take(len).collect()
+}
+
+/// Partial transcripts as they arrive while dictating: each one grows the
+/// text, and every third revises the last few words.
+fn partials(len: usize) -> Vec<String> {
+    let text = transcript(len);
+    let chars: Vec<char> = text.chars().collect();
+    (1..=10)
+        .map(|i| {
+            let end = chars.len() * i / 10;
+            if i % 3 == 0 && i < 10 {
+                let kept = end.saturating_sub(12);
+                chars[..kept]
+                    .iter()
+                    .copied()
+                    .chain("revised word".chars())
+                    .collect()
+            } else {
+                chars[..end].iter().collect()
+            }
+        })
+        .collect()
+}
+
+fn bench_edit_ops(c: &mut Criterion) {
+    let mut group = c.benchmark_group("edit_ops");
+    for len in [50, 300, 2000] {
+        let text = transcript(len);
+        let corrected: String = text.chars().take(len / 2).chain(['x']).collect();
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::new("type", len), &text, |b, text| {
+            b.iter(|| edit_ops(black_box(""), black_box(text)))
+        });
+        group.bench_with_input(BenchmarkId::new("correct", len), &text, |b, text| {
+            b.iter(|| edit_ops(black_box(text), black_box(&corrected)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_transitions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transition");
+    for len in [300, 2000] {
+        let partials = partials(len);
+        group.throughput(Throughput::Elements(partials.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(len),
+            &partials,
+            |b, partials| {
+                b.iter(|| {
+                    let mut state = TypingState::new();
+                    partials
+                        .iter()
+                        .map(|partial| state.transition(black_box(partial)).len())
+                        .sum::<usize>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_edit_ops, bench_transitions);
+criterion_main!(benches);
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+/// Decides how much of a partial transcript is typed.
+///
+/// Whisper tends to revise the last words of a transcript as more audio
+/// arrives, so typing every partial as is makes the tail flicker. Local
+/// agreement only commits the words that the last `agreement` partials
+/// agree on and holds back the rest until the final transcript. Committed
+/// text only grows until [`LocalAgreement::reset`].
+pub struct LocalAgreement {
+    agreement: usize,
+    /// The latest partials, newest first.
+    recent: VecDeque<String>,
+    committed: String,
+}
+
+/// Byte offsets just past each word of `text`.
+fn word_ends(text: &str) -> Vec<usize> {
+    let mut ends = Vec::new();
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if in_word && c.is_whitespace() {
+            ends.push(i);
+        }
+        in_word = !c.is_whitespace();
+    }
+    if in_word {
+        ends.push(text.len());
+    }
+    ends
+}
+
+/// Number of leading words `a` and `b` have in common.
+fn common_words(a: &str, b: &str) -> usize {
+    a.split_whitespace()
+        .zip(b.split_whitespace())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+impl LocalAgreement {
+    /// `agreement` partials must agree on a word to commit it; 1 commits
+    /// every partial in full.
+    pub fn new(agreement: usize) -> Self {
+        Self {
+            agreement: agreement.max(1),
+            recent: VecDeque::new(),
+            committed: String::new(),
+        }
+    }
+
+    pub fn set_agreement(&mut self, agreement: usize) {
+        self.agreement = agreement.max(1);
+        self.recent.truncate(self.agreement);
+    }
+
+    /// Records a partial transcript and returns the text committed so far.
+    pub fn update(&mut self, partial: &str) -> &str {
+        self.recent.push_front(partial.to_string());
+        self.recent.truncate(self.agreement);
+        if self.recent.len() < self.agreement {
+            return &self.committed;
+        }
+
+        let ends = word_ends(partial);
+        let agreed_words = self
+            .recent
+            .iter()
+            .skip(1)
+            .map(|other| common_words(partial, other))
+            .min()
+            .unwrap_or(ends.len());
+        // Words are appended past the committed ones even if the partials now
+        // disagree with those; once typed, a word stays until the final
+        // transcript corrects it.
+        let committed_words = self.committed.split_whitespace().count();
+        if agreed_words > committed_words {
+            let start = match committed_words {
+                0 => 0,
+                n => ends[n - 1],
+            };
+            self.committed
+                .push_str(&partial[start..ends[agreed_words - 1]]);
+        }
+        &self.committed
+    }
+
+    pub fn committed(&self) -> &str {
+        &self.committed
+    }
+
+    /// What the latest partial has after the committed words, which may
+    /// still change.
+    pub fn tentative(&self) -> &str {
+        let Some(latest) = self.recent.front() else {
+            return "";
+        };
+        let committed_words = self.committed.split_whitespace().count();
+        match committed_words {
+            0 => latest,
+            n => word_ends(latest)
+                .get(n - 1)
+                .map_or("", |&end| &latest[end..]),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.committed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_ends() {
+        assert_eq!(word_ends("  héllo  big world "), vec![8, 13, 19]);
+        assert!(word_ends(" ").is_empty());
+    }
+
+    #[test]
+    fn test_local_agreement() {
+        let mut policy = LocalAgreement::new(2);
+        // Nothing to agree with yet.
+        assert_eq!(policy.update("The quick brow"), "");
+        assert_eq!(policy.tentative(), "The quick brow");
+
+        assert_eq!(policy.update("The quick brown fox"), "The quick");
+        assert_eq!(policy.tentative(), " brown fox");
+
+        assert_eq!(
+            policy.update("The quick brown fox jumped"),
+            "The quick brown fox"
+        );
+        assert_eq!(policy.tentative(), " jumped");
+
+        // A revision of committed words does not retract them, but the
+        // words agreed on after them are still committed.
+        assert_eq!(
+            policy.update("A quick brown fox jumps over"),
+            "The quick brown fox"
+        );
+        assert_eq!(policy.tentative(), " jumps over");
+        assert_eq!(
+            policy.update("A quick brown fox jumps over it"),
+            "The quick brown fox jumps over"
+        );
+        assert_eq!(policy.tentative(), " it");
+
+        policy.reset();
+        assert_eq!(policy.committed(), "");
+        assert_eq!(policy.tentative(), "");
+    }
+
+    #[test]
+    fn test_local_agreement_of_one_commits_everything() {
+        let mut policy = LocalAgreement::new(1);
+        assert_eq!(policy.update("Hello"), "Hello");
+        assert_eq!(policy.update("Hello there "), "Hello there");
+        assert_eq!(policy.tentative(), " ");
+
+        let mut policy = LocalAgreement::new(3);
+        policy.update("one two three");
+        policy.update("one two four");
+        assert_eq!(policy.update("one too four"), "one");
+    }
+}
@@ -0,0 +1,815 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub const MODEL_SIZES: &[&str] = &["tiny", "base", "small", "medium", "large"];
+
+pub const OUTPUTS: &[&str] = &["auto", "xdo", "uinput", "wayland"];
+
+/// Name of the profile used by `ptt.key` and by commands that do not pick one.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse(toml::de::Error),
+    Invalid {
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "failed to read config {}: {}", path.display(), source)
+            }
+            ConfigError::Parse(e) => write!(f, "invalid config: {}", e),
+            ConfigError::Invalid { key, message } => write!(f, "invalid `{}`: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.into(),
+        message: message.into(),
+    }
+}
+
+/// How transcribed text is typed into the focused window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// Wayland when `WAYLAND_DISPLAY` is set, xdo when `DISPLAY` is, uinput otherwise.
+    #[default]
+    Auto,
+    Xdo,
+    Uinput,
+    Wayland,
+}
+
+impl Output {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Output::Auto => "auto",
+            Output::Xdo => "xdo",
+            Output::Uinput => "uinput",
+            Output::Wayland => "wayland",
+        }
+    }
+}
+
+impl std::str::FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Output::Auto),
+            "xdo" => Ok(Output::Xdo),
+            "uinput" => Ok(Output::Uinput),
+            "wayland" => Ok(Output::Wayland),
+            _ => Err(format!("`{}` is not one of {}", s, OUTPUTS.join(", "))),
+        }
+    }
+}
+
+/// Whether final transcripts are typed key by key or pasted from the clipboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InsertMode {
+    #[default]
+    Type,
+    Paste,
+}
+
+/// Picks typing or pasting for one application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PasteRule {
+    /// X11 window class or Wayland app id, compared case-insensitively.
+    pub app: String,
+    pub mode: InsertMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shortcut: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasteConfig {
+    /// Used for applications without a rule in `apps`.
+    pub mode: InsertMode,
+    /// Keys that paste, e.g. `ctrl+v`, `ctrl+shift+v` or `shift+Insert`.
+    pub shortcut: String,
+    /// Seconds the application gets to read the clipboard before it is restored.
+    pub restore_delay: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub apps: Vec<PasteRule>,
+}
+
+impl Default for PasteConfig {
+    fn default() -> Self {
+        Self {
+            mode: InsertMode::Type,
+            shortcut: "ctrl+v".to_string(),
+            restore_delay: 0.3,
+            apps: Vec::new(),
+        }
+    }
+}
+
+impl PasteConfig {
+    /// How text goes into `app`, and the shortcut that pastes there.
+    pub fn mode_for(&self, app: Option<&str>) -> (InsertMode, &str) {
+        let rule = app.and_then(|app| {
+            self.apps
+                .iter()
+                .find(|rule| rule.app.eq_ignore_ascii_case(app))
+        });
+        match rule {
+            Some(rule) => (
+                rule.mode,
+                rule.shortcut.as_deref().unwrap_or(&self.shortcut),
+            ),
+            None => (self.mode, &self.shortcut),
+        }
+    }
+}
+
+/// Saving recorded audio to reproduce transcriptions or build datasets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingsConfig {
+    /// Save each recording as a 16 kHz mono WAV file with a JSON sidecar.
+    pub enabled: bool,
+    /// Defaults to `vype/recordings` in the XDG data directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Recordings kept before the oldest are deleted; 0 keeps any number.
+    pub max_count: usize,
+    /// Megabytes the recordings may take up in total; 0 means no limit.
+    pub max_size_mb: u64,
+}
+
+impl Default for RecordingsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            max_count: 200,
+            max_size_mb: 500,
+        }
+    }
+}
+
+/// Discarding text Whisper likely made up from silence or noise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub enabled: bool,
+    /// Segments Whisper rates more likely than this to hold no speech are
+    /// dropped, unless their tokens are more confident than `min_logprob`.
+    pub no_speech_prob: f32,
+    /// Average token log probability under which a segment is not trusted.
+    pub min_logprob: f32,
+    /// RMS level, from 0 to 1, the loudest part of the audio must reach for
+    /// its transcript to be kept; 0 disables the check.
+    pub min_level: f32,
+    /// Transcripts of a whole recording that are nothing but one of these
+    /// phrases are dropped when Whisper doubts them by `no_speech_prob` or
+    /// `min_logprob`. Case and punctuation are ignored.
+    pub phrases: Vec<String>,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            no_speech_prob: 0.6,
+            min_logprob: -1.0,
+            min_level: 0.005,
+            phrases: [
+                "Thank you.",
+                "Thanks for watching!",
+                "Thank you for watching.",
+                "Please subscribe.",
+                "Subtitles by the Amara.org community",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// A substitution applied to transcripts after spoken commands.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replacement {
+    /// Words to find, ignoring case, or a regular expression with `regex`.
+    pub from: String,
+    /// With `regex`, `$1` or `${name}` insert what a group matched.
+    pub to: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+/// Rewriting of transcripts before they are typed; every rule is off
+/// unless turned on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostprocessConfig {
+    /// Turn "comma", "question mark" or "open paren" into the character.
+    pub spoken_punctuation: bool,
+    /// Turn "new line" and "new paragraph" into line breaks, and "all caps"
+    /// into the next word in capitals.
+    pub commands: bool,
+    /// Write numbers spoken as words, like "twenty three", as digits.
+    pub numbers: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub replacements: Vec<Replacement>,
+}
+
+impl PostprocessConfig {
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        for (i, replacement) in self.replacements.iter().enumerate() {
+            if replacement.from.is_empty() {
+                return Err(invalid(
+                    format!("{}.replacements[{}].from", key, i),
+                    "must not be empty",
+                ));
+            }
+            if replacement.regex
+                && let Err(e) = regex::Regex::new(&replacement.from)
+            {
+                return Err(invalid(
+                    format!("{}.replacements[{}].from", key, i),
+                    e.to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An extra push-to-talk key that records with the given profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BindingConfig {
+    pub key: String,
+    pub profile: String,
+}
+
+/// Per-profile overrides; unset values fall back to the top-level settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translate: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vocabulary: Option<Vec<String>>,
+    /// Replaces the top-level `postprocess` section as a whole.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postprocess: Option<PostprocessConfig>,
+}
+
+/// Transcription settings for one recording, after applying a profile's overrides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub model: Option<String>,
+    pub model_size: String,
+    pub language: String,
+    pub translate: bool,
+    pub output: Output,
+    pub prompt: String,
+    pub vocabulary: Vec<String>,
+    pub postprocess: PostprocessConfig,
+}
+
+impl Profile {
+    /// Text given to Whisper before the audio: the prompt followed by the
+    /// vocabulary as a list, which makes it more likely to spell those words
+    /// the same way.
+    pub fn initial_prompt(&self) -> String {
+        let mut prompt = self.prompt.trim().to_string();
+        if !self.vocabulary.is_empty() {
+            if !prompt.is_empty() {
+                prompt.push(' ');
+            }
+            prompt.push_str(&self.vocabulary.join(", "));
+            prompt.push('.');
+        }
+        prompt
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PttConfig {
+    pub key: String,
+    pub max_duration: u64,
+    pub partial_interval: f64,
+    /// Consecutive partial transcripts that must agree on a word before it is
+    /// typed; 1 types every partial as is.
+    pub partial_agreement: usize,
+    /// Seconds without speech after which a recording stops by itself; 0 disables it.
+    pub silence_stop: f64,
+    /// Cut silence from both ends of the audio before transcribing it.
+    pub trim_silence: bool,
+    /// Backspace over the partial transcripts typed so far when a recording
+    /// is cancelled.
+    pub erase_on_cancel: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bindings: Vec<BindingConfig>,
+}
+
+impl Default for PttConfig {
+    fn default() -> Self {
+        Self {
+            key: "F9".to_string(),
+            max_duration: 30,
+            partial_interval: 2.0,
+            partial_agreement: 2,
+            silence_stop: 0.0,
+            trim_silence: true,
+            erase_on_cancel: true,
+            bindings: Vec::new(),
+        }
+    }
+}
+
+/// Hands-free mode: record whenever speech is heard instead of on a key press.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VadConfig {
+    pub enabled: bool,
+    /// RMS level, from 0 to 1, above which audio counts as speech. Also used
+    /// by `ptt.silence_stop` and `ptt.trim_silence`.
+    pub threshold: f32,
+    /// Seconds of speech needed before a recording starts.
+    pub min_speech: f64,
+    /// Seconds of audio kept before detected speech and after it when trimming.
+    pub pre_roll: f64,
+    /// Seconds of trailing silence that end the recording.
+    pub silence: f64,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.015,
+            min_speech: 0.2,
+            pre_roll: 0.3,
+            silence: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub model_size: String,
+    pub language: String,
+    /// Translate speech to English instead of transcribing it in `language`.
+    pub translate: bool,
+    pub output: Output,
+    /// Milliseconds to wait between keystrokes, for applications that drop
+    /// keys typed too quickly.
+    pub key_delay_ms: u64,
+    /// Show desktop notifications when recording starts and stops.
+    pub notifications: bool,
+    /// Keep final transcripts in the history file for `vypec history`.
+    pub history: bool,
+    /// Text in the style of the speech, given to Whisper before the audio.
+    pub prompt: String,
+    /// Names and jargon Whisper should spell as written here.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary: Vec<String>,
+    /// Characters at the end of the previous dictation into the same window
+    /// given to Whisper as context; 0 disables it.
+    pub prompt_context: usize,
+    pub ptt: PttConfig,
+    pub vad: VadConfig,
+    pub paste: PasteConfig,
+    pub recordings: RecordingsConfig,
+    pub filter: FilterConfig,
+    pub postprocess: PostprocessConfig,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            model: None,
+            model_size: "medium".to_string(),
+            language: "en".to_string(),
+            translate: false,
+            output: Output::Auto,
+            key_delay_ms: 0,
+            notifications: true,
+            history: true,
+            prompt: String::new(),
+            vocabulary: Vec::new(),
+            prompt_context: 0,
+            ptt: PttConfig::default(),
+            vad: VadConfig::default(),
+            paste: PasteConfig::default(),
+            recordings: RecordingsConfig::default(),
+            filter: FilterConfig::default(),
+            postprocess: PostprocessConfig::default(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// Parses a TOML config; keys that are not set keep their default value.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml(&text)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Resolves a profile by name; [`DEFAULT_PROFILE`] uses the top-level settings.
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        let overrides = match name {
+            DEFAULT_PROFILE => ProfileConfig::default(),
+            _ => self.profiles.get(name)?.clone(),
+        };
+        // A profile that only picks a size should not inherit the top-level model file.
+        let model = match (&overrides.model, &overrides.model_size) {
+            (Some(model), _) => Some(model.clone()),
+            (None, Some(_)) => None,
+            (None, None) => self.model.clone(),
+        };
+        Some(Profile {
+            name: name.to_string(),
+            model,
+            model_size: overrides
+                .model_size
+                .unwrap_or_else(|| self.model_size.clone()),
+            language: overrides.language.unwrap_or_else(|| self.language.clone()),
+            translate: overrides.translate.unwrap_or(self.translate),
+            output: overrides.output.unwrap_or(self.output),
+            prompt: overrides.prompt.unwrap_or_else(|| self.prompt.clone()),
+            vocabulary: overrides
+                .vocabulary
+                .unwrap_or_else(|| self.vocabulary.clone()),
+            postprocess: overrides
+                .postprocess
+                .unwrap_or_else(|| self.postprocess.clone()),
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !MODEL_SIZES.contains(&self.model_size.as_str()) {
+            return Err(invalid(
+                "model_size",
+                format!(
+                    "`{}` is not one of {}",
+                    self.model_size,
+                    MODEL_SIZES.join(", ")
+                ),
+            ));
+        }
+        if self.language.trim().is_empty() {
+            return Err(invalid("language", "must not be empty"));
+        }
+        if self.vocabulary.iter().any(|word| word.trim().is_empty()) {
+            return Err(invalid("vocabulary", "must not contain empty words"));
+        }
+        if self.ptt.key.trim().is_empty() {
+            return Err(invalid("ptt.key", "must not be empty"));
+        }
+        if self.ptt.max_duration == 0 {
+            return Err(invalid("ptt.max_duration", "must be greater than 0"));
+        }
+        if !(self.ptt.partial_interval.is_finite() && self.ptt.partial_interval > 0.0) {
+            return Err(invalid("ptt.partial_interval", "must be greater than 0"));
+        }
+        if self.ptt.partial_agreement == 0 {
+            return Err(invalid("ptt.partial_agreement", "must be at least 1"));
+        }
+        if !(self.ptt.silence_stop.is_finite() && self.ptt.silence_stop >= 0.0) {
+            return Err(invalid("ptt.silence_stop", "must not be negative"));
+        }
+        if !(self.vad.threshold > 0.0 && self.vad.threshold <= 1.0) {
+            return Err(invalid("vad.threshold", "must be between 0 and 1"));
+        }
+        if !(self.vad.min_speech.is_finite() && self.vad.min_speech >= 0.0) {
+            return Err(invalid("vad.min_speech", "must not be negative"));
+        }
+        if !(self.vad.pre_roll.is_finite() && self.vad.pre_roll >= 0.0) {
+            return Err(invalid("vad.pre_roll", "must not be negative"));
+        }
+        if !(self.vad.silence.is_finite() && self.vad.silence > 0.0) {
+            return Err(invalid("vad.silence", "must be greater than 0"));
+        }
+        if !(0.0..=1.0).contains(&self.filter.no_speech_prob) {
+            return Err(invalid("filter.no_speech_prob", "must be between 0 and 1"));
+        }
+        if !(self.filter.min_logprob.is_finite() && self.filter.min_logprob <= 0.0) {
+            return Err(invalid("filter.min_logprob", "must not be positive"));
+        }
+        if !(0.0..=1.0).contains(&self.filter.min_level) {
+            return Err(invalid("filter.min_level", "must be between 0 and 1"));
+        }
+        self.postprocess.validate("postprocess")?;
+        if self.paste.shortcut.trim().is_empty() {
+            return Err(invalid("paste.shortcut", "must not be empty"));
+        }
+        if !(self.paste.restore_delay.is_finite() && self.paste.restore_delay >= 0.0) {
+            return Err(invalid("paste.restore_delay", "must not be negative"));
+        }
+        for (i, rule) in self.paste.apps.iter().enumerate() {
+            if rule.app.trim().is_empty() {
+                return Err(invalid(
+                    format!("paste.apps[{}].app", i),
+                    "must not be empty",
+                ));
+            }
+            if rule
+                .shortcut
+                .as_ref()
+                .is_some_and(|shortcut| shortcut.trim().is_empty())
+            {
+                return Err(invalid(
+                    format!("paste.apps[{}].shortcut", i),
+                    "must not be empty",
+                ));
+            }
+        }
+        for (i, binding) in self.ptt.bindings.iter().enumerate() {
+            if binding.key.trim().is_empty() {
+                return Err(invalid(
+                    format!("ptt.bindings[{}].key", i),
+                    "must not be empty",
+                ));
+            }
+            if self.profile(&binding.profile).is_none() {
+                return Err(invalid(
+                    format!("ptt.bindings[{}].profile", i),
+                    format!("unknown profile `{}`", binding.profile),
+                ));
+            }
+        }
+        for (name, profile) in &self.profiles {
+            if name == DEFAULT_PROFILE {
+                return Err(invalid(
+                    format!("profiles.{}", name),
+                    "is reserved for the top-level settings",
+                ));
+            }
+            if let Some(model_size) = &profile.model_size
+                && !MODEL_SIZES.contains(&model_size.as_str())
+            {
+                return Err(invalid(
+                    format!("profiles.{}.model_size", name),
+                    format!("`{}` is not one of {}", model_size, MODEL_SIZES.join(", ")),
+                ));
+            }
+            if profile
+                .language
+                .as_ref()
+                .is_some_and(|language| language.trim().is_empty())
+            {
+                return Err(invalid(
+                    format!("profiles.{}.language", name),
+                    "must not be empty",
+                ));
+            }
+            if profile
+                .vocabulary
+                .iter()
+                .flatten()
+                .any(|word| word.trim().is_empty())
+            {
+                return Err(invalid(
+                    format!("profiles.{}.vocabulary", name),
+                    "must not contain empty words",
+                ));
+            }
+            if let Some(postprocess) = &profile.postprocess {
+                postprocess.validate(&format!("profiles.{}.postprocess", name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config = AppConfig::from_toml(
+            r#"
+            language = "es"
+
+            [ptt]
+            key = "F8"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.language, "es");
+        assert_eq!(config.ptt.key, "F8");
+        assert_eq!(config.model_size, "medium");
+        assert_eq!(config.ptt.max_duration, 30);
+        assert_eq!(config.key_delay_ms, 0);
+        assert_eq!(config.ptt.silence_stop, 0.0);
+    }
+
+    #[test]
+    fn test_config_errors_name_key() {
+        let err = AppConfig::from_toml("model_size = \"huge\"").unwrap_err();
+        assert!(err.to_string().contains("model_size"));
+
+        let err = AppConfig::from_toml("[ptt]\nmax_duration = 0").unwrap_err();
+        assert!(err.to_string().contains("ptt.max_duration"));
+
+        let err = AppConfig::from_toml("[ptt]\npartial_agreement = 0").unwrap_err();
+        assert!(err.to_string().contains("ptt.partial_agreement"));
+
+        let err = AppConfig::from_toml("[vad]\nsilence = 0.0").unwrap_err();
+        assert!(err.to_string().contains("vad.silence"));
+
+        let err = AppConfig::from_toml("[filter]\nno_speech_prob = 1.5").unwrap_err();
+        assert!(err.to_string().contains("filter.no_speech_prob"));
+
+        let err = AppConfig::from_toml(
+            "[[profiles.code.postprocess.replacements]]\nfrom = \"(\"\nto = \"\"\nregex = true",
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("profiles.code.postprocess.replacements[0].from")
+        );
+
+        let err = AppConfig::from_toml("[ptt]\nkye = \"F8\"").unwrap_err();
+        assert!(err.to_string().contains("kye"));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config = AppConfig::from_toml(
+            r#"
+            language = "en"
+
+            [[ptt.bindings]]
+            key = "F10"
+            profile = "spanish"
+
+            [profiles.spanish]
+            language = "es"
+            model_size = "small"
+            output = "uinput"
+
+            [profiles.spanish.postprocess]
+            spoken_punctuation = true
+            "#,
+        )
+        .unwrap();
+
+        let spanish = config.profile("spanish").unwrap();
+        assert_eq!(spanish.language, "es");
+        assert!(!spanish.translate);
+        assert_eq!(spanish.model_size, "small");
+        assert_eq!(spanish.output, Output::Uinput);
+        assert!(spanish.postprocess.spoken_punctuation && !spanish.postprocess.commands);
+        let default = config.profile(DEFAULT_PROFILE).unwrap();
+        assert!(!default.postprocess.spoken_punctuation && !default.postprocess.commands);
+        assert_eq!(config.profile(DEFAULT_PROFILE).unwrap().language, "en");
+        assert!(config.profile("german").is_none());
+
+        let err = AppConfig::from_toml("[[ptt.bindings]]\nkey = \"F10\"\nprofile = \"german\"")
+            .unwrap_err();
+        assert!(err.to_string().contains("ptt.bindings[0].profile"));
+    }
+
+    #[test]
+    fn test_profile_prompts() {
+        let config = AppConfig::from_toml(
+            r#"
+            prompt = "Notes from the Rust team."
+            vocabulary = ["iceoryx", "Vype", "serde_json"]
+
+            [profiles.plain]
+            prompt = ""
+
+            [profiles.es]
+            language = "es"
+            prompt = "Hola."
+            vocabulary = []
+            "#,
+        )
+        .unwrap();
+
+        let cases = [
+            (
+                DEFAULT_PROFILE,
+                "Notes from the Rust team. iceoryx, Vype, serde_json.",
+            ),
+            ("plain", "iceoryx, Vype, serde_json."),
+            ("es", "Hola."),
+        ];
+        for (name, prompt) in cases {
+            assert_eq!(config.profile(name).unwrap().initial_prompt(), prompt);
+        }
+        assert_eq!(
+            AppConfig::default()
+                .profile(DEFAULT_PROFILE)
+                .unwrap()
+                .initial_prompt(),
+            ""
+        );
+
+        let err = AppConfig::from_toml("[profiles.es]\nvocabulary = [\" \"]").unwrap_err();
+        assert!(err.to_string().contains("profiles.es.vocabulary"));
+    }
+
+    #[test]
+    fn test_paste_rules() {
+        let config = AppConfig::from_toml(
+            r#"
+            [paste]
+            mode = "type"
+
+            [[paste.apps]]
+            app = "kitty"
+            mode = "paste"
+            shortcut = "ctrl+shift+v"
+
+            [[paste.apps]]
+            app = "firefox"
+            mode = "paste"
+
+            [[paste.apps]]
+            app = "org.gnome.TextEditor"
+            mode = "type"
+            "#,
+        )
+        .unwrap();
+
+        let cases = [
+            (None, InsertMode::Type, "ctrl+v"),
+            (Some("Alacritty"), InsertMode::Type, "ctrl+v"),
+            (Some("kitty"), InsertMode::Paste, "ctrl+shift+v"),
+            (Some("Firefox"), InsertMode::Paste, "ctrl+v"),
+            (Some("org.gnome.TextEditor"), InsertMode::Type, "ctrl+v"),
+        ];
+        for (app, mode, shortcut) in cases {
+            assert_eq!(config.paste.mode_for(app), (mode, shortcut), "{:?}", app);
+        }
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let config = AppConfig::default();
+        let parsed = AppConfig::from_toml(&config.to_toml()).unwrap();
+        assert_eq!(parsed.model_size, config.model_size);
+        assert_eq!(parsed.ptt.partial_interval, config.ptt.partial_interval);
+    }
+}
@@ -0,0 +1,411 @@
+/// A keyboard action at the cursor, which starts out right after the typed
+/// text. Counts repeat the key and `Type` holds a run of text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyOp {
+    Backspace(usize),
+    Delete(usize),
+    Type(String),
+    Left(usize),
+    Right(usize),
+}
+
+/// Cost of pressing a navigation or deletion key once.
+const KEY_COST: usize = 1;
+/// Cost of typing a character. Typing rewrites visible text and may need a
+/// keymap change, so moving the cursor over text is preferred to retyping it.
+const TYPE_COST: usize = 2;
+/// Largest word grid (old words × new words) diffed before falling back to
+/// retyping everything after the common prefix.
+const MAX_DIFF_CELLS: usize = 250_000;
+
+/// Appends `op` to `ops`, merging it into the last op when both repeat the
+/// same key or both type text, so sinks get as few calls as possible.
+pub fn push_op(ops: &mut Vec<KeyOp>, op: KeyOp) {
+    match (ops.last_mut(), op) {
+        (_, KeyOp::Type(text)) if text.is_empty() => {}
+        (_, KeyOp::Backspace(0) | KeyOp::Delete(0) | KeyOp::Left(0) | KeyOp::Right(0)) => {}
+        (Some(KeyOp::Type(last)), KeyOp::Type(text)) => last.push_str(&text),
+        (Some(KeyOp::Backspace(last)), KeyOp::Backspace(n))
+        | (Some(KeyOp::Delete(last)), KeyOp::Delete(n))
+        | (Some(KeyOp::Left(last)), KeyOp::Left(n))
+        | (Some(KeyOp::Right(last)), KeyOp::Right(n)) => *last += n,
+        (_, op) => ops.push(op),
+    }
+}
+
+/// Relative cost of performing `ops`, see [`edit_ops`].
+pub fn ops_cost(ops: &[KeyOp]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            KeyOp::Type(text) => text.chars().count() * TYPE_COST,
+            KeyOp::Backspace(n) | KeyOp::Delete(n) | KeyOp::Left(n) | KeyOp::Right(n) => {
+                n * KEY_COST
+            }
+        })
+        .sum()
+}
+
+pub struct TypingState {
+    typed: String,
+}
+
+impl TypingState {
+    pub fn new() -> Self {
+        Self {
+            typed: String::new(),
+        }
+    }
+
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    pub fn transition(&mut self, new_text: &str) -> Vec<KeyOp> {
+        let ops = edit_ops(&self.typed, new_text);
+        self.typed = new_text.to_string();
+        ops
+    }
+
+    pub fn clear(&mut self) {
+        self.typed.clear();
+    }
+}
+
+impl Default for TypingState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keys that turn `previous` into `current`, leaving the cursor at the end.
+///
+/// Changed words are found with a word-level diff and edited in place when
+/// moving the cursor to them is cheaper than backspacing and retyping the
+/// whole tail, so fixing one early word does not rewrite the sentence.
+pub fn edit_ops(previous: &str, current: &str) -> Vec<KeyOp> {
+    if previous == current {
+        return vec![];
+    }
+
+    let old: Vec<char> = previous.chars().collect();
+    let new: Vec<char> = current.chars().collect();
+
+    let mut best = retype_ops(&old, &new);
+    if let Some(hunks) = diff(&old, &new) {
+        for ops in [
+            backward_ops(old.len(), new.len(), &hunks),
+            forward_ops(old.len(), new.len(), &hunks),
+        ] {
+            if ops_cost(&ops) < ops_cost(&best) {
+                best = ops;
+            }
+        }
+    }
+    best
+}
+
+/// `len` characters of the old text from `start` on are replaced by `text`.
+struct Hunk<'a> {
+    start: usize,
+    len: usize,
+    text: &'a [char],
+}
+
+/// Words with their trailing whitespace, so that joining them gives `chars` back.
+fn words(chars: &[char]) -> Vec<&[char]> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        if chars[i - 1].is_whitespace() && !chars[i].is_whitespace() {
+            words.push(&chars[start..i]);
+            start = i;
+        }
+    }
+    if start < chars.len() {
+        words.push(&chars[start..]);
+    }
+    words
+}
+
+/// The changes between `old` and `new` from a longest common subsequence of
+/// their words, trimmed to the characters that actually differ. `None` when
+/// the texts are too long to diff.
+fn diff<'a>(old: &[char], new: &'a [char]) -> Option<Vec<Hunk<'a>>> {
+    let old_words = words(old);
+    let new_words = words(new);
+    let (n, m) = (old_words.len(), new_words.len());
+    if n * m > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // lcs[at(i, j)]: common subsequence length of old_words[i..] and new_words[j..].
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[at(i, j)] = if old_words[i] == new_words[j] {
+                lcs[at(i + 1, j + 1)] + 1
+            } else {
+                lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    // Where the unmatched words since the last match start, in both texts.
+    let mut pending = None;
+    while i < n || j < m {
+        if i < n && j < m && old_words[i] == new_words[j] {
+            if let Some((old_start, new_start)) = pending.take() {
+                hunks.push(hunk(old, new, old_start..old_pos, new_start..new_pos));
+            }
+            old_pos += old_words[i].len();
+            new_pos += new_words[j].len();
+            i += 1;
+            j += 1;
+        } else {
+            pending.get_or_insert((old_pos, new_pos));
+            if j < m && (i == n || lcs[at(i, j + 1)] >= lcs[at(i + 1, j)]) {
+                new_pos += new_words[j].len();
+                j += 1;
+            } else {
+                old_pos += old_words[i].len();
+                i += 1;
+            }
+        }
+    }
+    if let Some((old_start, new_start)) = pending {
+        hunks.push(hunk(old, new, old_start..old_pos, new_start..new_pos));
+    }
+    Some(hunks)
+}
+
+/// A hunk for replacing `old[old_range]` with `new[new_range]`, without the
+/// characters both ranges start or end with.
+fn hunk<'a>(
+    old: &[char],
+    new: &'a [char],
+    old_range: std::ops::Range<usize>,
+    new_range: std::ops::Range<usize>,
+) -> Hunk<'a> {
+    let (mut start, mut end) = (old_range.start, old_range.end);
+    let (mut new_start, mut new_end) = (new_range.start, new_range.end);
+    while start < end && new_start < new_end && old[start] == new[new_start] {
+        start += 1;
+        new_start += 1;
+    }
+    while start < end && new_start < new_end && old[end - 1] == new[new_end - 1] {
+        end -= 1;
+        new_end -= 1;
+    }
+    Hunk {
+        start,
+        len: end - start,
+        text: &new[new_start..new_end],
+    }
+}
+
+/// Backspaces everything after the common prefix and types the rest.
+fn retype_ops(old: &[char], new: &[char]) -> Vec<KeyOp> {
+    let common_prefix_len = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+
+    let mut ops = Vec::new();
+    push_op(&mut ops, KeyOp::Backspace(old.len() - common_prefix_len));
+    push_op(
+        &mut ops,
+        KeyOp::Type(new[common_prefix_len..].iter().collect()),
+    );
+    ops
+}
+
+/// Edits the hunks from last to first, backspacing each from its end.
+fn backward_ops(old_len: usize, new_len: usize, hunks: &[Hunk]) -> Vec<KeyOp> {
+    let mut ops = Vec::new();
+    let mut cursor = old_len;
+    for hunk in hunks.iter().rev() {
+        push_op(&mut ops, KeyOp::Left(cursor - (hunk.start + hunk.len)));
+        push_op(&mut ops, KeyOp::Backspace(hunk.len));
+        push_op(&mut ops, KeyOp::Type(hunk.text.iter().collect()));
+        // Text left of the cursor is still unchanged from `old`.
+        cursor = hunk.start + hunk.text.len();
+    }
+    push_op(&mut ops, KeyOp::Right(new_len - cursor));
+    ops
+}
+
+/// Edits the hunks from first to last, deleting each from its start.
+fn forward_ops(old_len: usize, new_len: usize, hunks: &[Hunk]) -> Vec<KeyOp> {
+    let mut ops = Vec::new();
+    let Some(first) = hunks.first() else {
+        return ops;
+    };
+    push_op(&mut ops, KeyOp::Left(old_len - first.start));
+    let mut cursor = first.start;
+    // Characters removed and added by the hunks already edited.
+    let (mut removed, mut added) = (0, 0);
+    for hunk in hunks {
+        let start = hunk.start - removed + added;
+        push_op(&mut ops, KeyOp::Right(start - cursor));
+        push_op(&mut ops, KeyOp::Delete(hunk.len));
+        push_op(&mut ops, KeyOp::Type(hunk.text.iter().collect()));
+        cursor = start + hunk.text.len();
+        removed += hunk.len;
+        added += hunk.text.len();
+    }
+    push_op(&mut ops, KeyOp::Right(new_len - cursor));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Applies `ops` to `previous` with the cursor starting at the end.
+    fn replay(previous: &str, ops: &[KeyOp]) -> String {
+        let mut text: Vec<char> = previous.chars().collect();
+        let mut cursor = text.len();
+        for op in ops {
+            match op {
+                KeyOp::Backspace(n) => {
+                    text.drain(cursor - n..cursor);
+                    cursor -= n;
+                }
+                KeyOp::Delete(n) => {
+                    text.drain(cursor..cursor + n);
+                }
+                KeyOp::Left(n) => cursor -= n,
+                KeyOp::Right(n) => {
+                    cursor += n;
+                    assert!(cursor <= text.len(), "moved past the end");
+                }
+                KeyOp::Type(s) => {
+                    for c in s.chars() {
+                        text.insert(cursor, c);
+                        cursor += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(cursor, text.len(), "cursor must end after the text");
+        text.into_iter().collect()
+    }
+
+    #[test]
+    fn test_typing_state() {
+        let mut state = TypingState::new();
+
+        let ops = state.transition("hello");
+        assert_eq!(state.typed(), "hello");
+        assert!(!ops.is_empty());
+
+        let ops = state.transition("hello world");
+        assert_eq!(state.typed(), "hello world");
+        assert!(!ops.is_empty());
+
+        state.clear();
+        assert_eq!(state.typed(), "");
+    }
+
+    #[test]
+    fn test_edit_ops() {
+        let ops = edit_ops("", "hello");
+        assert_eq!(ops, vec![KeyOp::Type("hello".to_string())]);
+
+        let ops = edit_ops("hello", "hello world");
+        assert_eq!(ops, vec![KeyOp::Type(" world".to_string())]);
+
+        let ops = edit_ops("hello world", "");
+        assert_eq!(ops, vec![KeyOp::Backspace(11)]);
+
+        let ops = edit_ops("héllo there", "héllo thé");
+        assert_eq!(ops, vec![KeyOp::Backspace(3), KeyOp::Type("é".to_string())]);
+    }
+
+    #[test]
+    fn test_edit_ops_moves_cursor() {
+        // One early word changes: fix it in place instead of retyping the tail.
+        let ops = edit_ops(
+            "I went to the store and bought milk",
+            "I want to the store and bought milk",
+        );
+        assert_eq!(
+            ops,
+            vec![
+                KeyOp::Left(31),
+                KeyOp::Backspace(1),
+                KeyOp::Type("a".to_string()),
+                KeyOp::Right(31),
+            ]
+        );
+
+        // Insertions only: walking forward avoids crossing the inserted text twice.
+        let ops = edit_ops("a b c d", "a xxxxx b c d yyyyy");
+        assert_eq!(
+            ops,
+            vec![
+                KeyOp::Left(5),
+                KeyOp::Type("xxxxx ".to_string()),
+                KeyOp::Right(5),
+                KeyOp::Type(" yyyyy".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_push_op() {
+        let mut ops = Vec::new();
+        push_op(&mut ops, KeyOp::Backspace(2));
+        push_op(&mut ops, KeyOp::Backspace(3));
+        push_op(&mut ops, KeyOp::Type(String::new()));
+        push_op(&mut ops, KeyOp::Type("ab".to_string()));
+        push_op(&mut ops, KeyOp::Left(0));
+        push_op(&mut ops, KeyOp::Type("c".to_string()));
+        push_op(&mut ops, KeyOp::Right(1));
+        assert_eq!(
+            ops,
+            vec![
+                KeyOp::Backspace(5),
+                KeyOp::Type("abc".to_string()),
+                KeyOp::Right(1),
+            ]
+        );
+    }
+
+    /// Sentences built from a few words, so that texts share some of them.
+    fn sentence() -> impl Strategy<Value = String> {
+        let words = [
+            "the ", "cat ", "sat", "on ", "mat. ", "é", "naïve ", " ", "\n", "x",
+        ];
+        prop::collection::vec(prop::sample::select(words.to_vec()), 0..24)
+            .prop_map(|words| words.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn edit_ops_replay(previous in sentence(), current in sentence()) {
+            let ops = edit_ops(&previous, &current);
+            prop_assert_eq!(replay(&previous, &ops), current.clone());
+
+            let old: Vec<char> = previous.chars().collect();
+            let new: Vec<char> = current.chars().collect();
+            prop_assert!(ops_cost(&ops) <= ops_cost(&retype_ops(&old, &new)));
+        }
+
+        #[test]
+        fn edit_ops_replay_any_text(previous in "[ab é\\n]{0,16}", current in "[ab é\\n]{0,16}") {
+            let ops = edit_ops(&previous, &current);
+            prop_assert_eq!(replay(&previous, &ops), current);
+            // Coalesced: no empty ops and no two neighbours of the same kind.
+            for pair in ops.windows(2) {
+                prop_assert_ne!(
+                    std::mem::discriminant(&pair[0]),
+                    std::mem::discriminant(&pair[1])
+                );
+            }
+            prop_assert!(!ops.iter().any(|op| ops_cost(std::slice::from_ref(op)) == 0));
+        }
+    }
+}
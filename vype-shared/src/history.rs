@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A final transcript, as stored in the history file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix time in milliseconds when the transcript was typed.
+    pub timestamp: u64,
+    /// Seconds of audio recorded.
+    pub duration: f64,
+    pub profile: String,
+    pub model: String,
+    pub language: String,
+    pub text: String,
+    /// Application the text was typed into, when it could be found out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Whether the entry is from `since` (Unix milliseconds) or later and
+    /// contains `search`, ignoring case.
+    pub fn matches(&self, search: Option<&str>, since: Option<u64>) -> bool {
+        since.is_none_or(|since| self.timestamp >= since)
+            && search.is_none_or(|search| self.text.to_lowercase().contains(&search.to_lowercase()))
+    }
+}
+
+/// `history.jsonl` in the XDG data directory, one JSON entry per line.
+pub fn history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("vype").join("history.jsonl"))
+}
+
+/// Appends `entry` to the history file at `path`, creating it if needed.
+pub fn append_history(path: &Path, entry: &HistoryEntry) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    // A single write keeps lines whole when two daemons append at once.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Reads the history file, oldest entry first. Entries are numbered from 1
+/// in this order. Lines that do not parse, such as one cut short by a crash,
+/// are skipped but keep their number. A missing file is an empty history.
+pub fn read_history(path: &Path) -> io::Result<Vec<(usize, HistoryEntry)>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        if let Ok(entry) = serde_json::from_str(&line?) {
+            entries.push((i + 1, entry));
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, text: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            duration: 1.5,
+            profile: "default".to_string(),
+            model: "ggml-small.en.bin".to_string(),
+            language: "en".to_string(),
+            text: text.to_string(),
+            window: None,
+        }
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vype").join("history.jsonl");
+        assert!(read_history(&path).unwrap().is_empty());
+
+        let first = entry(1000, "Hello there.");
+        let second = HistoryEntry {
+            window: Some("firefox".to_string()),
+            ..entry(2000, "General Kenobi.")
+        };
+        append_history(&path, &first).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"timestamp\":15").unwrap();
+        file.write_all(b"\n").unwrap();
+        append_history(&path, &second).unwrap();
+
+        assert_eq!(read_history(&path).unwrap(), vec![(1, first), (3, second)]);
+    }
+
+    #[test]
+    fn test_history_entry_matches() {
+        let entry = entry(2000, "Open the Pod bay doors");
+        assert!(entry.matches(None, None));
+        assert!(entry.matches(Some("pod BAY"), Some(2000)));
+        assert!(!entry.matches(Some("hal"), None));
+        assert!(!entry.matches(None, Some(2001)));
+    }
+}
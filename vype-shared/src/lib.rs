@@ -1,4 +1,21 @@
 use iceoryx2::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod agreement;
+mod config;
+mod edit;
+mod history;
+mod postprocess;
+
+pub use agreement::LocalAgreement;
+pub use config::{
+    AppConfig, BindingConfig, ConfigError, DEFAULT_PROFILE, FilterConfig, InsertMode, MODEL_SIZES,
+    OUTPUTS, Output, PasteConfig, PasteRule, PostprocessConfig, Profile, ProfileConfig, PttConfig,
+    RecordingsConfig, Replacement, VadConfig,
+};
+pub use edit::{KeyOp, TypingState, edit_ops, ops_cost, push_op};
+pub use history::{HistoryEntry, append_history, history_path, read_history};
+pub use postprocess::PostProcessor;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ZeroCopySend)]
 #[repr(C)]
@@ -7,6 +24,11 @@ pub enum PttEventType {
     StopRecording = 1,
     PartialTranscribe = 2,
     ToggleRecording = 3,
+    QueryStatus = 4,
+    ReloadConfig = 5,
+    CancelRecording = 6,
+    UndoDictation = 7,
+    RetypeEntry = 8,
 }
 
 #[derive(Debug, Clone, Copy, ZeroCopySend)]
@@ -14,112 +36,193 @@ pub enum PttEventType {
 pub struct PttEvent {
     pub event_type: PttEventType,
     pub timestamp: u64,
+    /// Profile to record with; empty selects the default profile.
+    pub profile: IpcString<32>,
+    /// Number of the history entry to type for [`PttEventType::RetypeEntry`].
+    pub entry: u64,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum KeyOp {
-    Backspace(usize),
-    Delete(usize),
-    Type(char),
-    Left(usize),
-    Right(usize),
+pub fn unix_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 
-pub struct TypingState {
-    typed: String,
+/// Fixed-capacity UTF-8 string that can be sent over shared memory.
+///
+/// Text longer than `N` bytes is truncated at the last char boundary that fits.
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[repr(C)]
+pub struct IpcString<const N: usize> {
+    len: u32,
+    bytes: [u8; N],
 }
 
-impl TypingState {
-    pub fn new() -> Self {
+impl<const N: usize> IpcString<N> {
+    pub fn new(text: &str) -> Self {
+        let mut len = text.len().min(N);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        let mut bytes = [0u8; N];
+        bytes[..len].copy_from_slice(&text.as_bytes()[..len]);
         Self {
-            typed: String::new(),
+            len: len as u32,
+            bytes,
         }
     }
 
-    pub fn typed(&self) -> &str {
-        &self.typed
+    pub fn as_str(&self) -> &str {
+        let len = (self.len as usize).min(N);
+        std::str::from_utf8(&self.bytes[..len]).unwrap_or_default()
     }
+}
 
-    pub fn transition(&mut self, new_text: &str) -> Vec<KeyOp> {
-        let ops = edit_ops(&self.typed, new_text);
-        self.typed = new_text.to_string();
-        ops
+impl<const N: usize> Default for IpcString<N> {
+    fn default() -> Self {
+        Self::new("")
     }
+}
 
-    pub fn clear(&mut self) {
-        self.typed.clear();
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ZeroCopySend)]
+#[repr(C)]
+pub enum ResponseStatus {
+    Ok = 0,
+    Error = 1,
 }
 
-impl Default for TypingState {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Snapshot of the daemon's state, attached to every [`PttResponse`].
+#[derive(Debug, Clone, Copy, Default, ZeroCopySend)]
+#[repr(C)]
+pub struct DaemonStatus {
+    pub recording: bool,
+    /// Time since the current recording started, zero when idle.
+    pub recording_elapsed_ms: u64,
+    pub uptime_ms: u64,
+    pub model_path: IpcString<256>,
+    pub model_size: IpcString<16>,
+    pub language: IpcString<16>,
+    /// Transcription backend `vyped` was compiled with (`cpu`, `vulkan` or `cuda`).
+    pub backend: IpcString<16>,
+    pub ptt_key: IpcString<32>,
+    /// Profile of the current recording, empty when idle.
+    pub profile: IpcString<32>,
+    /// Typing backend in use, see `Output`.
+    pub output: IpcString<16>,
+    /// Hands-free mode is enabled.
+    pub vad: bool,
 }
 
-pub fn edit_ops(previous: &str, current: &str) -> Vec<KeyOp> {
-    if previous == current {
-        return vec![];
+/// Reply sent by the daemon for every [`PttEvent`] it receives.
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[repr(C)]
+pub struct PttResponse {
+    pub status: ResponseStatus,
+    pub message: IpcString<256>,
+    /// Daemon state after handling the request.
+    pub daemon: DaemonStatus,
+}
+
+impl PttResponse {
+    pub fn ok(daemon: DaemonStatus, message: &str) -> Self {
+        Self {
+            status: ResponseStatus::Ok,
+            message: IpcString::new(message),
+            daemon,
+        }
     }
 
-    if previous.is_empty() {
-        return current.chars().map(KeyOp::Type).collect();
+    pub fn error(daemon: DaemonStatus, message: &str) -> Self {
+        Self {
+            status: ResponseStatus::Error,
+            message: IpcString::new(message),
+            daemon,
+        }
     }
 
-    if current.is_empty() {
-        return vec![KeyOp::Backspace(previous.chars().count())];
+    pub fn is_ok(&self) -> bool {
+        self.status == ResponseStatus::Ok
     }
+}
 
-    let prev_chars: Vec<char> = previous.chars().collect();
-    let curr_chars: Vec<char> = current.chars().collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ZeroCopySend)]
+#[repr(C)]
+pub enum DaemonEventKind {
+    RecordingStarted = 0,
+    RecordingStopped = 1,
+    PartialText = 2,
+    FinalText = 3,
+    Error = 4,
+    MaxDurationReached = 5,
+    SilenceTimeout = 6,
+    RecordingCancelled = 7,
+    DictationUndone = 8,
+    TranscriptDiscarded = 9,
+}
 
-    let common_prefix_len = prev_chars
-        .iter()
-        .zip(curr_chars.iter())
-        .take_while(|(a, b)| a == b)
-        .count();
+impl DaemonEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DaemonEventKind::RecordingStarted => "recording_started",
+            DaemonEventKind::RecordingStopped => "recording_stopped",
+            DaemonEventKind::PartialText => "partial_text",
+            DaemonEventKind::FinalText => "final_text",
+            DaemonEventKind::Error => "error",
+            DaemonEventKind::MaxDurationReached => "max_duration_reached",
+            DaemonEventKind::SilenceTimeout => "silence_timeout",
+            DaemonEventKind::RecordingCancelled => "recording_cancelled",
+            DaemonEventKind::DictationUndone => "dictation_undone",
+            DaemonEventKind::TranscriptDiscarded => "transcript_discarded",
+        }
+    }
+}
 
-    let mut ops = Vec::new();
+/// Published by the daemon on `vype/events` whenever its state changes or text is produced.
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[repr(C)]
+pub struct DaemonEvent {
+    pub kind: DaemonEventKind,
+    pub timestamp: u64,
+    /// Transcript for text events, error message for [`DaemonEventKind::Error`], empty otherwise.
+    pub text: IpcString<4096>,
+    /// Bytes at the start of `text` the daemon has committed to. The rest of a
+    /// partial transcript is tentative and may still change.
+    pub committed_len: u32,
+}
 
-    let backspace_count = prev_chars.len() - common_prefix_len;
-    if backspace_count > 0 {
-        ops.push(KeyOp::Backspace(backspace_count));
+impl DaemonEvent {
+    pub fn new(kind: DaemonEventKind, text: &str) -> Self {
+        Self::partial(kind, text, text.len())
     }
 
-    for c in curr_chars[common_prefix_len..].iter() {
-        ops.push(KeyOp::Type(*c));
+    /// An event whose text is only committed up to `committed_len` bytes.
+    pub fn partial(kind: DaemonEventKind, text: &str, committed_len: usize) -> Self {
+        let text = IpcString::new(text);
+        Self {
+            kind,
+            timestamp: unix_timestamp_ms(),
+            committed_len: committed_len.min(text.as_str().len()) as u32,
+            text,
+        }
     }
 
-    ops
-}
-
-#[derive(Debug, Clone)]
-pub struct PttConfig {
-    pub key: String,
-    pub max_duration: u64,
-    pub partial_interval: f64,
-}
+    pub fn committed(&self) -> &str {
+        self.split().0
+    }
 
-#[derive(Debug, Clone)]
-pub struct AppConfig {
-    pub model: Option<String>,
-    pub model_size: String,
-    pub language: String,
-    pub ptt: PttConfig,
-}
+    pub fn tentative(&self) -> &str {
+        self.split().1
+    }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            model: None,
-            model_size: "medium".to_string(),
-            language: "en".to_string(),
-            ptt: PttConfig {
-                key: "F9".to_string(),
-                max_duration: 60,
-                partial_interval: 2.0,
-            },
+    fn split(&self) -> (&str, &str) {
+        let text = self.text.as_str();
+        let mut len = (self.committed_len as usize).min(text.len());
+        while !text.is_char_boundary(len) {
+            len -= 1;
         }
+        text.split_at(len)
     }
 }
 
@@ -128,30 +231,26 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_typing_state() {
-        let mut state = TypingState::new();
+    fn test_ipc_string() {
+        let s = IpcString::<16>::new("hello");
+        assert_eq!(s.as_str(), "hello");
 
-        let ops = state.transition("hello");
-        assert_eq!(state.typed(), "hello");
-        assert!(!ops.is_empty());
+        let s = IpcString::<4>::new("héllo");
+        assert_eq!(s.as_str(), "hél");
 
-        let ops = state.transition("hello world");
-        assert_eq!(state.typed(), "hello world");
-        assert!(!ops.is_empty());
-
-        state.clear();
-        assert_eq!(state.typed(), "");
+        let response = PttResponse::error(DaemonStatus::default(), "No input device");
+        assert!(!response.is_ok());
+        assert_eq!(response.message.as_str(), "No input device");
     }
 
     #[test]
-    fn test_edit_ops() {
-        let ops = edit_ops("", "hello");
-        assert_eq!(ops.len(), 5);
-
-        let ops = edit_ops("hello", "hello world");
-        assert_eq!(ops.len(), 6); // " world"
+    fn test_daemon_event_split() {
+        let event = DaemonEvent::partial(DaemonEventKind::PartialText, "Hello wörld", 5);
+        assert_eq!(event.committed(), "Hello");
+        assert_eq!(event.tentative(), " wörld");
 
-        let ops = edit_ops("hello world", "");
-        assert_eq!(ops, vec![KeyOp::Backspace(11)]);
+        let event = DaemonEvent::new(DaemonEventKind::FinalText, "Hello");
+        assert_eq!(event.committed(), "Hello");
+        assert_eq!(event.tentative(), "");
     }
 }
@@ -0,0 +1,447 @@
+use regex::{NoExpand, Regex};
+
+use crate::config::PostprocessConfig;
+
+/// Which neighbours a piece of output is written against without a space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Glue {
+    None,
+    Left,
+    Right,
+    Both,
+}
+
+/// Spoken punctuation, what it is written as and how it sticks to the words
+/// around it. Everything here can be typed on a US layout.
+const PUNCTUATION: &[(&str, &str, Glue)] = &[
+    ("comma", ",", Glue::Left),
+    ("period", ".", Glue::Left),
+    ("full stop", ".", Glue::Left),
+    ("question mark", "?", Glue::Left),
+    ("exclamation mark", "!", Glue::Left),
+    ("exclamation point", "!", Glue::Left),
+    ("colon", ":", Glue::Left),
+    ("semicolon", ";", Glue::Left),
+    ("ellipsis", "...", Glue::Left),
+    ("dash", "-", Glue::None),
+    ("hyphen", "-", Glue::Both),
+    ("open paren", "(", Glue::Right),
+    ("open parenthesis", "(", Glue::Right),
+    ("close paren", ")", Glue::Left),
+    ("close parenthesis", ")", Glue::Left),
+    ("open bracket", "[", Glue::Right),
+    ("close bracket", "]", Glue::Left),
+    ("open quote", "\"", Glue::Right),
+    ("close quote", "\"", Glue::Left),
+];
+
+const BREAKS: &[(&str, &str, Glue)] = &[
+    ("new line", "\n", Glue::Both),
+    ("new paragraph", "\n\n", Glue::Both),
+];
+
+/// Punctuation Whisper puts after a word itself, which gives way to the
+/// punctuation spoken after it.
+const AUTO_PUNCTUATION: &[char] = &[',', '.', ';', ':', '!', '?'];
+
+const UNITS: &[&str] = &[
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const TENS: &[&str] = &[
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const SCALES: &[(&str, u64)] = &[
+    ("thousand", 1_000),
+    ("million", 1_000_000),
+    ("billion", 1_000_000_000),
+];
+
+/// Lowercase `word` without the punctuation around it.
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// The punctuation at the end of `word`.
+fn suffix(word: &str) -> &str {
+    let trimmed = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+    &word[trimmed.len()..]
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// A number spelled out in words, read one word at a time.
+#[derive(Debug, Default)]
+struct Number {
+    /// Thousands, millions and billions read so far.
+    total: u64,
+    /// The part below the last scale word.
+    current: u64,
+    /// The smallest scale word read so far.
+    scale: Option<u64>,
+}
+
+impl Number {
+    /// Adds `word`, or returns false if it does not continue the number.
+    fn push(&mut self, word: &str) -> bool {
+        let below_hundred = self.current % 100;
+        if let Some(value) = UNITS.iter().position(|unit| *unit == word) {
+            let value = value as u64;
+            // "three" may follow "twenty" or "hundred", but not "five" or "twelve".
+            let fits = if value >= 10 {
+                below_hundred == 0
+            } else {
+                value > 0 && below_hundred.is_multiple_of(10) && below_hundred != 10
+            };
+            if fits {
+                self.current += value;
+            }
+            return fits;
+        }
+        if let Some(tens) = TENS.iter().position(|tens| *tens == word) {
+            if below_hundred != 0 {
+                return false;
+            }
+            self.current += (tens as u64 + 2) * 10;
+            return true;
+        }
+        if word == "hundred" {
+            if !(1..100).contains(&self.current) {
+                return false;
+            }
+            self.current *= 100;
+            return true;
+        }
+        if let Some(&(_, scale)) = SCALES.iter().find(|(name, _)| *name == word) {
+            if self.current == 0 || self.scale.is_some_and(|smaller| scale >= smaller) {
+                return false;
+            }
+            self.total += self.current * scale;
+            self.current = 0;
+            self.scale = Some(scale);
+            return true;
+        }
+        false
+    }
+
+    fn value(&self) -> u64 {
+        self.total + self.current
+    }
+}
+
+/// Reads a number spelled out at the start of `words`, like "twenty-three"
+/// or "one hundred and five". Returns it with the number of words it takes
+/// up; numbers below ten on their own are left as words.
+fn parse_number(words: &[&str]) -> Option<(u64, usize)> {
+    let mut number = Number::default();
+    let mut used = 0;
+    let mut i = 0;
+    while i < words.len() {
+        let word = normalize(words[i]);
+        // "and" only belongs to the number when more of it follows.
+        if word == "and"
+            && used > 0
+            && number.current.is_multiple_of(100)
+            && suffix(words[i]).is_empty()
+            && words
+                .get(i + 1)
+                .is_some_and(|next| continues_number(&normalize(next)))
+        {
+            i += 1;
+            continue;
+        }
+        if word.is_empty() || !word.split('-').all(|part| number.push(part)) {
+            break;
+        }
+        i += 1;
+        used = i;
+        // Punctuation after a word ends the number.
+        if !suffix(words[i - 1]).is_empty() {
+            break;
+        }
+    }
+    let value = number.value();
+    (used > 1 || value >= 10).then_some((value, used))
+}
+
+/// Whether `word` can continue a number after "and".
+fn continues_number(word: &str) -> bool {
+    word.split('-')
+        .all(|part| UNITS[1..].contains(&part) || TENS.contains(&part))
+}
+
+struct Piece {
+    text: String,
+    glue: Glue,
+}
+
+/// Rewrites transcripts following a [`PostprocessConfig`]: spoken
+/// punctuation and commands first, then numbers, then the replacements.
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessor {
+    spoken_punctuation: bool,
+    commands: bool,
+    numbers: bool,
+    /// Compiled replacements, and whether `$` in the replacement is literal.
+    replacements: Vec<(Regex, String, bool)>,
+}
+
+impl PostProcessor {
+    pub fn new(config: &PostprocessConfig) -> Result<Self, regex::Error> {
+        let mut replacements = Vec::new();
+        for replacement in &config.replacements {
+            let regex = if replacement.regex {
+                Regex::new(&replacement.from)?
+            } else {
+                // Whole words only, so "vype" does not match inside "vyped".
+                let from = &replacement.from;
+                let start = if from.starts_with(|c: char| c.is_alphanumeric()) {
+                    r"\b"
+                } else {
+                    ""
+                };
+                let end = if from.ends_with(|c: char| c.is_alphanumeric()) {
+                    r"\b"
+                } else {
+                    ""
+                };
+                Regex::new(&format!("(?i){}{}{}", start, regex::escape(from), end))?
+            };
+            replacements.push((regex, replacement.to.clone(), !replacement.regex));
+        }
+        Ok(Self {
+            spoken_punctuation: config.spoken_punctuation,
+            commands: config.commands,
+            numbers: config.numbers,
+            replacements,
+        })
+    }
+
+    /// The spoken punctuation or break at the start of `words`, with the
+    /// number of words it takes up.
+    fn symbol(&self, words: &[&str]) -> Option<(&'static str, Glue, usize)> {
+        let tables = [
+            (self.spoken_punctuation, PUNCTUATION),
+            (self.commands, BREAKS),
+        ];
+        tables
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .flat_map(|(_, table)| table.iter())
+            .filter_map(|&(phrase, text, glue)| {
+                let n = phrase.split(' ').count();
+                let spoken = words.get(..n)?.iter().map(|word| normalize(word));
+                spoken
+                    .eq(phrase.split(' ').map(str::to_string))
+                    .then_some((text, glue, n))
+            })
+            .max_by_key(|&(_, _, n)| n)
+    }
+
+    /// Rewrites `text`. Whitespace at its start is kept, so segments can
+    /// be processed one by one.
+    pub fn process(&self, text: &str) -> String {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut capitalize_next = false;
+        let mut i = 0;
+        while i < words.len() {
+            if let Some((symbol, glue, n)) = self.symbol(&words[i..]) {
+                if symbol.chars().all(|c| AUTO_PUNCTUATION.contains(&c))
+                    && let Some(last) = pieces.last_mut()
+                    && last.glue == Glue::None
+                {
+                    let trimmed = last.text.trim_end_matches(AUTO_PUNCTUATION).len();
+                    last.text.truncate(trimmed);
+                }
+                pieces.push(Piece {
+                    text: symbol.to_string(),
+                    glue,
+                });
+                capitalize_next = matches!(symbol, "." | "?" | "!" | "\n" | "\n\n");
+                i += n;
+                continue;
+            }
+
+            let mut word = words[i].to_string();
+            let mut n = 1;
+            if self.commands
+                && normalize(words[i]) == "all"
+                && words
+                    .get(i + 1)
+                    .is_some_and(|caps| normalize(caps) == "caps")
+                && let Some(next) = words.get(i + 2)
+            {
+                word = next.to_uppercase();
+                n = 3;
+            } else if self.numbers
+                && let Some((value, used)) = parse_number(&words[i..])
+            {
+                word = format!("{}{}", value, suffix(words[i + used - 1]));
+                n = used;
+            }
+            if capitalize_next {
+                word = capitalize(&word);
+                capitalize_next = false;
+            }
+            pieces.push(Piece {
+                text: word,
+                glue: Glue::None,
+            });
+            i += n;
+        }
+
+        let mut out = text[..text.len() - text.trim_start().len()].to_string();
+        let mut glued = true;
+        for piece in &pieces {
+            if !glued && !matches!(piece.glue, Glue::Left | Glue::Both) {
+                out.push(' ');
+            }
+            out.push_str(&piece.text);
+            glued = matches!(piece.glue, Glue::Right | Glue::Both);
+        }
+
+        for (regex, to, literal) in &self.replacements {
+            out = if *literal {
+                regex.replace_all(&out, NoExpand(to)).into_owned()
+            } else {
+                regex.replace_all(&out, to.as_str()).into_owned()
+            };
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Replacement;
+
+    fn processor(numbers: bool, replacements: &[(&str, &str, bool)]) -> PostProcessor {
+        let config = PostprocessConfig {
+            spoken_punctuation: true,
+            commands: true,
+            numbers,
+            replacements: replacements
+                .iter()
+                .map(|&(from, to, regex)| Replacement {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    regex,
+                })
+                .collect(),
+        };
+        PostProcessor::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_spoken_punctuation_and_commands() {
+        let processor = processor(false, &[]);
+        let cases = [
+            ("Hello there", "Hello there"),
+            ("Hello comma world period", "Hello, world."),
+            // Whisper's own punctuation gives way to the spoken one.
+            ("Hello, comma, world. Period.", "Hello, world."),
+            ("Is it done question mark", "Is it done?"),
+            ("Dear Sam colon new line thanks", "Dear Sam:\nThanks"),
+            ("First. New paragraph. Second.", "First.\n\nSecond."),
+            ("call it open paren now close paren", "call it (now)"),
+            ("He said open quote hi close quote", "He said \"hi\""),
+            ("a well hyphen known fact", "a well-known fact"),
+            ("wait dash what", "wait - what"),
+            ("this is all caps important", "this is IMPORTANT"),
+            ("ends with all caps", "ends with all caps"),
+            ("done period next one", "done. Next one"),
+            (
+                "It took twenty three seconds",
+                "It took twenty three seconds",
+            ),
+            (" Leading space", " Leading space"),
+            ("", ""),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(processor.process(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let processor = processor(true, &[]);
+        let cases = [
+            ("It took twenty three seconds", "It took 23 seconds"),
+            ("twenty-three", "23"),
+            ("one hundred and five people", "105 people"),
+            ("two thousand twenty four", "2024"),
+            ("three million four hundred thousand", "3400000"),
+            ("one of them", "one of them"),
+            ("twelve items", "12 items"),
+            ("rock and roll", "rock and roll"),
+            ("one two three", "one two three"),
+            ("fifty and a half", "50 and a half"),
+            ("page twenty, line four", "page 20, line four"),
+            ("Zero issues", "Zero issues"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(processor.process(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_replacements() {
+        let processor = processor(
+            false,
+            &[
+                ("vype", "Vype", false),
+                ("see see", "cc", false),
+                (r"\b(\d+) percent\b", "$1%", true),
+                ("dollar", "$1", false),
+            ],
+        );
+        let cases = [
+            ("start vype and vyped", "start Vype and vyped"),
+            ("VYPE rocks", "Vype rocks"),
+            ("see see the team", "cc the team"),
+            ("up 5 percent", "up 5%"),
+            ("one dollar", "one $1"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(processor.process(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_off_by_default() {
+        let processor = PostProcessor::new(&PostprocessConfig::default()).unwrap();
+        assert_eq!(
+            processor.process("comma new line all caps x"),
+            "comma new line all caps x"
+        );
+    }
+}
@@ -1,12 +1,16 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::{Parser, ValueEnum};
-use iceoryx2::port::publisher::Publisher;
+use iceoryx2::port::client::Client;
 use iceoryx2::prelude::*;
-use log::info;
+use log::{debug, info};
+use serde::Serialize;
+use std::io::Write;
 use std::thread;
-use std::time::Duration;
-use std::time::{SystemTime, UNIX_EPOCH};
-use vype_shared::{PttEvent, PttEventType};
+use std::time::{Duration, Instant};
+use vype_shared::{
+    DaemonEvent, DaemonEventKind, DaemonStatus, HistoryEntry, IpcString, PttEvent, PttEventType,
+    PttResponse, history_path, read_history, unix_timestamp_ms,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "vypec")]
@@ -15,6 +19,33 @@ struct Args {
     #[arg(value_enum, default_value_t = Command::Toggle)]
     command: Command,
 
+    /// Number of the history entry to type for `retype`
+    #[arg(value_name = "N")]
+    entry: Option<u64>,
+
+    #[arg(
+        short = 't',
+        long = "timeout",
+        default_value = "60",
+        value_name = "SECS"
+    )]
+    timeout: f64,
+
+    /// Profile to record with for `start` and `toggle` (default: the daemon's default profile)
+    #[arg(short = 'p', long = "profile", value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Only list history entries containing this text, ignoring case
+    #[arg(long = "search", value_name = "TEXT")]
+    search: Option<String>,
+
+    /// Only list history entries from this local date or time on (e.g. 2024-03-01 or 2024-03-01T14:30)
+    #[arg(long = "since", value_name = "DATE")]
+    since: Option<String>,
+
+    #[arg(long = "json")]
+    json: bool,
+
     #[arg(short = 'v', long = "verbose")]
     verbose: bool,
 }
@@ -23,38 +54,242 @@ struct Args {
 enum Command {
     Start,
     Stop,
+    Cancel,
+    Undo,
     Toggle,
     Partial,
+    Status,
+    Reload,
+    Watch,
+    History,
+    Retype,
+}
+
+impl Command {
+    /// Control event sent to the daemon, `None` for commands that only listen.
+    fn event_type(self) -> Option<PttEventType> {
+        match self {
+            Command::Start => Some(PttEventType::StartRecording),
+            Command::Stop => Some(PttEventType::StopRecording),
+            Command::Cancel => Some(PttEventType::CancelRecording),
+            Command::Undo => Some(PttEventType::UndoDictation),
+            Command::Toggle => Some(PttEventType::ToggleRecording),
+            Command::Partial => Some(PttEventType::PartialTranscribe),
+            Command::Status => Some(PttEventType::QueryStatus),
+            Command::Reload => Some(PttEventType::ReloadConfig),
+            Command::Retype => Some(PttEventType::RetypeEntry),
+            Command::Watch | Command::History => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StatusReport<'a> {
+    recording: bool,
+    recording_elapsed_secs: f64,
+    model_path: &'a str,
+    model_size: &'a str,
+    language: &'a str,
+    backend: &'a str,
+    ptt_key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<&'a str>,
+    output: &'a str,
+    vad: bool,
+    uptime_secs: f64,
+}
+
+impl<'a> From<&'a DaemonStatus> for StatusReport<'a> {
+    fn from(status: &'a DaemonStatus) -> Self {
+        Self {
+            recording: status.recording,
+            recording_elapsed_secs: status.recording_elapsed_ms as f64 / 1000.0,
+            model_path: status.model_path.as_str(),
+            model_size: status.model_size.as_str(),
+            language: status.language.as_str(),
+            backend: status.backend.as_str(),
+            ptt_key: status.ptt_key.as_str(),
+            profile: Some(status.profile.as_str()).filter(|p| !p.is_empty()),
+            output: status.output.as_str(),
+            vad: status.vad,
+            uptime_secs: status.uptime_ms as f64 / 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct WatchEvent<'a> {
+    event: &'static str,
+    timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+    /// Split of a partial transcript into typed and still changing text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    committed: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tentative: Option<&'a str>,
+}
+
+impl<'a> From<&'a DaemonEvent> for WatchEvent<'a> {
+    fn from(event: &'a DaemonEvent) -> Self {
+        let partial = event.kind == DaemonEventKind::PartialText;
+        Self {
+            event: event.kind.as_str(),
+            timestamp: event.timestamp,
+            text: Some(event.text.as_str()).filter(|text| !text.is_empty()),
+            committed: partial.then(|| event.committed()),
+            tentative: partial.then(|| event.tentative()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryLine<'a> {
+    id: usize,
+    #[serde(flatten)]
+    entry: &'a HistoryEntry,
+}
+
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (hours, minutes, seconds) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+fn print_status(status: &DaemonStatus, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(&StatusReport::from(status))?);
+        return Ok(());
+    }
+
+    if status.recording {
+        println!(
+            "Recording: yes ({}, profile {})",
+            format_duration(status.recording_elapsed_ms),
+            status.profile.as_str()
+        );
+    } else {
+        println!("Recording: no");
+    }
+    println!(
+        "Model:     {} ({})",
+        status.model_path.as_str(),
+        status.model_size.as_str()
+    );
+    println!("Language:  {}", status.language.as_str());
+    println!("Backend:   {}", status.backend.as_str());
+    println!("PTT key:   {}", status.ptt_key.as_str());
+    println!("Output:    {}", status.output.as_str());
+    println!("VAD:       {}", if status.vad { "on" } else { "off" });
+    println!("Uptime:    {}", format_duration(status.uptime_ms));
+    Ok(())
+}
+
+/// Unix milliseconds at the start of a local date, or at a local date and time.
+fn parse_since(since: &str) -> Result<u64> {
+    let datetime = match since.parse::<jiff::civil::DateTime>() {
+        Ok(datetime) => datetime,
+        Err(_) => since
+            .parse::<jiff::civil::Date>()
+            .map_err(|e| anyhow::anyhow!("invalid date `{}`: {}", since, e))?
+            .into(),
+    };
+    let zoned = datetime.to_zoned(jiff::tz::TimeZone::system())?;
+    Ok(zoned.timestamp().as_millisecond().max(0) as u64)
 }
 
-impl From<Command> for PttEventType {
-    fn from(value: Command) -> Self {
-        match value {
-            Command::Start => PttEventType::StartRecording,
-            Command::Stop => PttEventType::StopRecording,
-            Command::Toggle => PttEventType::ToggleRecording,
-            Command::Partial => PttEventType::PartialTranscribe,
+fn print_history(search: Option<&str>, since: Option<&str>, json: bool) -> Result<()> {
+    let Some(path) = history_path() else {
+        bail!("Could not determine the data directory");
+    };
+    let since = since.map(parse_since).transpose()?;
+    let entries = read_history(&path)?;
+
+    for (id, entry) in entries
+        .iter()
+        .filter(|(_, entry)| entry.matches(search, since))
+    {
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string(&HistoryLine { id: *id, entry })?
+            );
+            continue;
         }
+        let time = jiff::Timestamp::from_millisecond(entry.timestamp as i64)?
+            .to_zoned(jiff::tz::TimeZone::system());
+        println!(
+            "{:>5}  {}  {:>6}  {}",
+            id,
+            time.strftime("%Y-%m-%d %H:%M"),
+            format_duration((entry.duration * 1000.0) as u64),
+            entry.text
+        );
     }
+    Ok(())
 }
 
 fn send_ptt_event(
-    publisher: &Publisher<ipc::Service, PttEvent, ()>,
+    client: &Client<ipc::Service, PttEvent, (), PttResponse, ()>,
     event_type: PttEventType,
-) -> Result<()> {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64;
-
+    profile: Option<&str>,
+    entry: u64,
+    timeout: Duration,
+) -> Result<PttResponse> {
     let event = PttEvent {
         event_type,
-        timestamp,
+        timestamp: unix_timestamp_ms(),
+        profile: IpcString::new(profile.unwrap_or_default()),
+        entry,
     };
 
-    let sample = publisher.loan_uninit()?;
-    let sample = sample.write_payload(event);
-    sample.send()?;
+    let pending_response = client.send_copy(event)?;
+    if pending_response.number_of_server_connections() == 0 {
+        bail!("No vyped daemon is running");
+    }
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(response) = pending_response.receive()? {
+            return Ok(*response.payload());
+        }
+        if Instant::now() >= deadline {
+            bail!("Timed out waiting for vyped to respond");
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Streams daemon events to stdout as newline-delimited JSON until interrupted.
+fn watch_events(node: &Node<ipc::Service>) -> Result<()> {
+    let event_service = node
+        .service_builder(&"vype/events".try_into()?)
+        .publish_subscribe::<DaemonEvent>()
+        .max_publishers(1)
+        .max_subscribers(8)
+        .subscriber_max_buffer_size(32)
+        .open_or_create()?;
+
+    let subscriber = event_service
+        .subscriber_builder()
+        .buffer_size(32)
+        .create()?;
+    let mut stdout = std::io::stdout();
+
+    while node.wait(Duration::from_millis(10)).is_ok() {
+        while let Some(sample) = subscriber.receive()? {
+            let line = serde_json::to_string(&WatchEvent::from(sample.payload()))?;
+            writeln!(stdout, "{}", line)?;
+            stdout.flush()?;
+        }
+    }
+
     Ok(())
 }
 
@@ -69,22 +304,47 @@ fn main() -> Result<()> {
         })
         .init();
 
+    // The history file is read directly, without a daemon.
+    if let Command::History = args.command {
+        return print_history(args.search.as_deref(), args.since.as_deref(), args.json);
+    }
+    let entry = match (args.command, args.entry) {
+        (Command::Retype, None) => bail!("`retype` needs the number of a history entry"),
+        (_, entry) => entry.unwrap_or_default(),
+    };
+
     let node = NodeBuilder::new()
         .name(&"vypec".try_into()?)
         .create::<ipc::Service>()?;
 
-    let ptt_service = node
-        .service_builder(&"vype/ptt_events".try_into()?)
-        .publish_subscribe::<PttEvent>()
-        .max_publishers(8)
-        .max_subscribers(4)
+    let Some(event_type) = args.command.event_type() else {
+        return watch_events(&node);
+    };
+
+    let control_service = node
+        .service_builder(&"vype/control".try_into()?)
+        .request_response::<PttEvent, PttResponse>()
+        .max_clients(8)
+        .max_servers(1)
         .open_or_create()?;
 
-    let publisher = ptt_service.publisher_builder().create()?;
-    let event_type: PttEventType = args.command.into();
-    send_ptt_event(&publisher, event_type)?;
-    thread::sleep(Duration::from_millis(75));
+    let client = control_service.client_builder().create()?;
+    debug!("Sending command: {:?}", args.command);
+    let response = send_ptt_event(
+        &client,
+        event_type,
+        args.profile.as_deref(),
+        entry,
+        Duration::from_secs_f64(args.timeout),
+    )?;
 
-    info!("Sent command: {:?}", args.command);
+    if !response.is_ok() {
+        bail!("{}", response.message.as_str());
+    }
+
+    match args.command {
+        Command::Status => print_status(&response.daemon, args.json)?,
+        _ => info!("{}", response.message.as_str()),
+    }
     Ok(())
 }
@@ -3,11 +3,14 @@ use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Device, Stream};
 use std::sync::{Arc, Mutex};
 
+use crate::resample::downmix;
+
 pub struct CpalAudioSource {
     device: Device,
     sample_rate: u32,
     channels: u16,
     stream: Option<Stream>,
+    /// Captured audio, downmixed to mono.
     buffer: Arc<Mutex<Vec<f32>>>,
 }
 
@@ -55,10 +58,7 @@ impl CpalAudioSource {
                     if channels == 1 {
                         buf.extend_from_slice(data);
                     } else {
-                        for chunk in data.chunks(channels) {
-                            let mono: f32 = chunk.iter().sum::<f32>() / channels as f32;
-                            buf.push(mono);
-                        }
+                        buf.extend(downmix(data, channels));
                     }
                 },
                 |err| eprintln!("Audio stream error: {}", err),
@@ -83,11 +83,17 @@ impl CpalAudioSource {
         self.sample_rate
     }
 
-    pub fn channels(&self) -> u16 {
-        self.channels
+    /// Samples captured after the first `start` in the buffer.
+    pub fn samples_since(&self, start: usize) -> Vec<f32> {
+        let buf = self.buffer.lock().unwrap();
+        buf.get(start..).unwrap_or_default().to_vec()
     }
 
-    pub fn get_current_samples(&self) -> Vec<f32> {
-        self.buffer.lock().unwrap().clone()
+    /// Drops the oldest samples so at most `max_len` remain, returning how many were dropped.
+    pub fn trim_front(&self, max_len: usize) -> usize {
+        let mut buf = self.buffer.lock().unwrap();
+        let excess = buf.len().saturating_sub(max_len);
+        buf.drain(..excess);
+        excess
     }
 }
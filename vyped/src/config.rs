@@ -0,0 +1,101 @@
+use anyhow::Result;
+use log::info;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use vype_shared::{AppConfig, Output};
+
+/// Values given on the command line, which take precedence over the config file.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub model: Option<String>,
+    pub model_size: Option<String>,
+    pub language: Option<String>,
+    pub key: Option<String>,
+    pub output: Option<Output>,
+    pub max_duration: Option<u64>,
+    pub partial_interval: Option<f64>,
+    pub vad: Option<bool>,
+}
+
+impl ConfigOverrides {
+    fn apply(&self, config: &mut AppConfig) {
+        if let Some(model) = &self.model {
+            config.model = Some(model.clone());
+        }
+        if let Some(model_size) = &self.model_size {
+            config.model_size = model_size.clone();
+        }
+        if let Some(language) = &self.language {
+            config.language = language.clone();
+        }
+        if let Some(key) = &self.key {
+            config.ptt.key = key.clone();
+        }
+        if let Some(output) = self.output {
+            config.output = output;
+        }
+        if let Some(max_duration) = self.max_duration {
+            config.ptt.max_duration = max_duration;
+        }
+        if let Some(partial_interval) = self.partial_interval {
+            config.ptt.partial_interval = partial_interval;
+        }
+        if let Some(vad) = self.vad {
+            config.vad.enabled = vad;
+        }
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("vype").join("config.toml"))
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Where the daemon's configuration comes from, so it can be re-read on reload.
+pub struct ConfigSource {
+    path: Option<PathBuf>,
+    /// An explicit `--config` must exist; the default location is optional.
+    required: bool,
+    overrides: ConfigOverrides,
+    loaded_modified_at: Option<SystemTime>,
+}
+
+impl ConfigSource {
+    pub fn new(path: Option<PathBuf>, overrides: ConfigOverrides) -> Self {
+        let required = path.is_some();
+        Self {
+            path: path.or_else(default_config_path),
+            required,
+            overrides,
+            loaded_modified_at: None,
+        }
+    }
+
+    /// Loads the config file and applies the command-line overrides.
+    pub fn load(&mut self) -> Result<AppConfig> {
+        // Remember the file version even if it fails to parse, so a broken file
+        // is reported once rather than on every poll.
+        self.loaded_modified_at = self.path.as_deref().and_then(modified_at);
+
+        let mut config = match &self.path {
+            Some(path) if self.required || path.exists() => {
+                let config = AppConfig::load(path)?;
+                info!("Loaded configuration from {}", path.display());
+                config
+            }
+            _ => AppConfig::default(),
+        };
+
+        self.overrides.apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Whether the config file was created, modified or removed since the last load.
+    pub fn changed(&self) -> bool {
+        self.path.as_deref().and_then(modified_at) != self.loaded_modified_at
+    }
+}
@@ -0,0 +1,1057 @@
+use anyhow::{Context, Result, bail};
+use iceoryx2::port::publisher::Publisher;
+use iceoryx2::prelude::*;
+use log::{error, info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use vype_shared::{
+    AppConfig, DaemonEvent, DaemonEventKind, DaemonStatus, HistoryEntry, IpcString, LocalAgreement,
+    append_history, history_path, read_history, unix_timestamp_ms,
+};
+use vype_shared::{
+    DEFAULT_PROFILE, InsertMode, KeyOp, Output, PostProcessor, Profile, PttEvent, PttEventType,
+    TypingState,
+};
+
+use crate::BACKEND;
+use crate::audio::CpalAudioSource;
+use crate::config::ConfigSource;
+use crate::focus::{Window, active_window};
+use crate::hotkey::PttBinding;
+use crate::model::resolve_model_path;
+use crate::paste::Paster;
+use crate::recordings::{Sidecar, save_recording};
+use crate::sink::{Shortcut, Sinks};
+use crate::stream::StreamingTranscript;
+use crate::transcriber::WhisperTranscriber;
+use crate::vad::{VadEvent, VoiceDetector, trim_silence};
+use crate::worker::{Job, JobKind, Transcription, TranscriptionWorker};
+
+/// A control request; `Start` and `Toggle` name the profile to record with.
+#[derive(Debug, Clone)]
+pub enum ControlMsg {
+    Start(String),
+    Stop,
+    Cancel,
+    Undo,
+    /// Types the history entry with this number again.
+    Retype(usize),
+    Partial,
+    Toggle(String),
+    Status,
+    Reload,
+}
+
+impl From<&PttEvent> for ControlMsg {
+    fn from(value: &PttEvent) -> Self {
+        let profile = match value.profile.as_str() {
+            "" => DEFAULT_PROFILE.to_string(),
+            name => name.to_string(),
+        };
+        match value.event_type {
+            PttEventType::StartRecording => ControlMsg::Start(profile),
+            PttEventType::StopRecording => ControlMsg::Stop,
+            PttEventType::CancelRecording => ControlMsg::Cancel,
+            PttEventType::UndoDictation => ControlMsg::Undo,
+            PttEventType::RetypeEntry => ControlMsg::Retype(value.entry as usize),
+            PttEventType::PartialTranscribe => ControlMsg::Partial,
+            PttEventType::ToggleRecording => ControlMsg::Toggle(profile),
+            PttEventType::QueryStatus => ControlMsg::Status,
+            PttEventType::ReloadConfig => ControlMsg::Reload,
+        }
+    }
+}
+
+/// Dictations kept for `vypec undo`.
+const UNDO_HISTORY: usize = 20;
+
+fn notify(title: &str, body: &str) {
+    if let Err(e) = notify_rust::Notification::new()
+        .summary(title)
+        .body(body)
+        .timeout(2000)
+        .show()
+    {
+        error!("Failed to show notification: {}", e);
+    }
+}
+
+/// Settings the key listener and partial timer threads read while the daemon runs.
+#[derive(Clone)]
+pub struct SharedState {
+    pub is_recording: Arc<AtomicBool>,
+    pub ptt_bindings: Arc<Mutex<Vec<PttBinding>>>,
+    pub partial_interval_ms: Arc<AtomicU64>,
+}
+
+impl SharedState {
+    pub fn new(config: &AppConfig) -> Result<Self> {
+        Ok(Self {
+            is_recording: Arc::new(AtomicBool::new(false)),
+            ptt_bindings: Arc::new(Mutex::new(parse_bindings(config)?)),
+            partial_interval_ms: Arc::new(AtomicU64::new(partial_interval_ms(config))),
+        })
+    }
+}
+
+/// `ptt.key` records with the default profile, followed by the extra `ptt.bindings`.
+fn parse_bindings(config: &AppConfig) -> Result<Vec<PttBinding>> {
+    let mut bindings = vec![PttBinding {
+        hotkey: config.ptt.key.parse().context("invalid `ptt.key`")?,
+        profile: DEFAULT_PROFILE.to_string(),
+    }];
+    for (i, binding) in config.ptt.bindings.iter().enumerate() {
+        bindings.push(PttBinding {
+            hotkey: binding
+                .key
+                .parse()
+                .with_context(|| format!("invalid `ptt.bindings[{}].key`", i))?,
+            profile: binding.profile.clone(),
+        });
+    }
+    Ok(bindings)
+}
+
+fn profiles(config: &AppConfig) -> impl Iterator<Item = Profile> + '_ {
+    std::iter::once(DEFAULT_PROFILE)
+        .chain(config.profiles.keys().map(String::as_str))
+        .filter_map(|name| config.profile(name))
+}
+
+/// The post-processor of each profile, by name.
+fn postprocessors(config: &AppConfig) -> Result<HashMap<String, PostProcessor>> {
+    profiles(config)
+        .map(|profile| {
+            let postprocessor = PostProcessor::new(&profile.postprocess)
+                .with_context(|| format!("invalid replacement in profile `{}`", profile.name))?;
+            Ok((profile.name, postprocessor))
+        })
+        .collect()
+}
+
+fn outputs(config: &AppConfig) -> impl Iterator<Item = Output> + '_ {
+    profiles(config).map(|profile| profile.output)
+}
+
+/// Fails on the first `paste` shortcut that cannot be sent.
+fn check_shortcuts(config: &AppConfig) -> Result<()> {
+    config
+        .paste
+        .shortcut
+        .parse::<Shortcut>()
+        .context("invalid `paste.shortcut`")?;
+    for (i, rule) in config.paste.apps.iter().enumerate() {
+        if let Some(shortcut) = &rule.shortcut {
+            shortcut
+                .parse::<Shortcut>()
+                .with_context(|| format!("invalid `paste.apps[{}].shortcut`", i))?;
+        }
+    }
+    Ok(())
+}
+
+/// The focused window, or `None` when it cannot be found out.
+fn focused_window() -> Option<Window> {
+    active_window().unwrap_or_else(|e| {
+        warn!("Failed to find the focused window: {:#}", e);
+        None
+    })
+}
+
+fn partial_interval_ms(config: &AppConfig) -> u64 {
+    (config.ptt.partial_interval * 1000.0) as u64
+}
+
+fn default_profile(config: &AppConfig) -> Profile {
+    config
+        .profile(DEFAULT_PROFILE)
+        .expect("the default profile always exists")
+}
+
+/// Whisper models used by the configured profiles, each loaded once.
+struct Models {
+    /// Model path for each profile name.
+    paths: HashMap<String, String>,
+    transcribers: HashMap<String, Arc<WhisperTranscriber>>,
+}
+
+impl Models {
+    /// Loads the models `config` needs that are not in `loaded` already.
+    fn load(config: &AppConfig, loaded: Option<&Models>) -> Result<Self> {
+        let mut paths = HashMap::new();
+        for profile in profiles(config) {
+            let model_path = resolve_model_path(&profile)?;
+            paths.insert(profile.name, model_path);
+        }
+
+        let mut transcribers = HashMap::new();
+        for model_path in paths.values() {
+            let reusable =
+                loaded.is_some_and(|models| models.transcribers.contains_key(model_path));
+            if !reusable && !transcribers.contains_key(model_path) {
+                info!("Loading model from {}", model_path);
+                transcribers.insert(
+                    model_path.clone(),
+                    Arc::new(WhisperTranscriber::new(model_path)?),
+                );
+            }
+        }
+
+        Ok(Self {
+            paths,
+            transcribers,
+        })
+    }
+
+    /// Takes over the models from `previous` that are still in use.
+    fn reuse(&mut self, mut previous: Models) {
+        for model_path in self.paths.values() {
+            if let Some(transcriber) = previous.transcribers.remove(model_path) {
+                self.transcribers.insert(model_path.clone(), transcriber);
+            }
+        }
+    }
+
+    fn path(&self, profile: &Profile) -> &str {
+        &self.paths[&profile.name]
+    }
+
+    fn transcriber(&self, profile: &Profile) -> Arc<WhisperTranscriber> {
+        self.transcribers[self.path(profile)].clone()
+    }
+}
+
+/// What hands-free mode is doing with the audio stream. `fed` counts the
+/// buffered samples already passed to the [`VoiceDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VadState {
+    /// Disabled, or a recording it did not start owns the stream.
+    Off,
+    Listening {
+        fed: usize,
+    },
+    /// Recording an utterance it detected.
+    Recording {
+        fed: usize,
+    },
+}
+
+/// A stopped recording waiting for its final transcript.
+struct Finishing {
+    recording: u64,
+    profile: Profile,
+    model: String,
+    /// Seconds of audio recorded.
+    duration: f64,
+    /// The recorded samples, kept when `recordings` are saved.
+    audio: Option<Vec<f32>>,
+    typing_state: TypingState,
+    stream: StreamingTranscript,
+    postprocessor: PostProcessor,
+    paste_shortcut: Option<Shortcut>,
+}
+
+/// Text a recording put into a window, which `vypec undo` can take out again.
+struct Dictation {
+    text: String,
+    output: Output,
+    window: Option<Window>,
+}
+
+/// Recording, transcription and typing state owned by the daemon's control loop.
+pub struct Daemon {
+    config: AppConfig,
+    config_source: ConfigSource,
+    models: Models,
+    postprocessors: HashMap<String, PostProcessor>,
+    started_at: Instant,
+    shared: SharedState,
+    recording_started_at: Option<Instant>,
+    /// Number of the current or last recording.
+    recording: u64,
+    /// Profile of the current recording; kept until it has been transcribed.
+    profile: Profile,
+    typing_state: TypingState,
+    /// Decides which words of the partial transcripts get typed.
+    agreement: LocalAgreement,
+    stream: StreamingTranscript,
+    /// End of the dictation before the current recording, for `prompt_context`.
+    context: String,
+    worker: TranscriptionWorker,
+    finishing: VecDeque<Finishing>,
+    /// Finished dictations, oldest first.
+    dictations: VecDeque<Dictation>,
+    audio_source: CpalAudioSource,
+    vad: VoiceDetector,
+    vad_state: VadState,
+    /// Buffered samples of the current recording measured for `ptt.silence_stop`.
+    silence_fed: usize,
+    sinks: Sinks,
+    paster: Paster,
+    /// Set when the current recording is pasted rather than typed.
+    paste_shortcut: Option<Shortcut>,
+    events: Publisher<ipc::Service, DaemonEvent, ()>,
+}
+
+impl Daemon {
+    pub fn new(
+        config: AppConfig,
+        config_source: ConfigSource,
+        shared: SharedState,
+        events: Publisher<ipc::Service, DaemonEvent, ()>,
+    ) -> Result<Self> {
+        let started_at = Instant::now();
+        check_shortcuts(&config)?;
+        let models = Models::load(&config, None)?;
+        let postprocessors = postprocessors(&config)?;
+        let sinks = Sinks::open(outputs(&config), None)?;
+        let audio_source = CpalAudioSource::new()?;
+        let profile = default_profile(&config);
+        let vad = VoiceDetector::new(&config.vad, audio_source.sample_rate());
+        let agreement = LocalAgreement::new(config.ptt.partial_agreement);
+
+        Ok(Self {
+            config,
+            config_source,
+            models,
+            postprocessors,
+            started_at,
+            shared,
+            recording_started_at: None,
+            recording: 0,
+            profile,
+            typing_state: TypingState::new(),
+            agreement,
+            stream: StreamingTranscript::default(),
+            context: String::new(),
+            worker: TranscriptionWorker::spawn()?,
+            finishing: VecDeque::new(),
+            dictations: VecDeque::new(),
+            audio_source,
+            vad,
+            vad_state: VadState::Off,
+            silence_fed: 0,
+            sinks,
+            paster: Paster::default(),
+            paste_shortcut: None,
+            events,
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.shared.is_recording.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> DaemonStatus {
+        let recording = self.is_recording();
+        // Report the active profile's settings while recording, the defaults otherwise.
+        let profile = if recording {
+            self.profile.clone()
+        } else {
+            default_profile(&self.config)
+        };
+        DaemonStatus {
+            recording,
+            recording_elapsed_ms: self
+                .recording_started_at
+                .map(|t| t.elapsed().as_millis() as u64)
+                .unwrap_or_default(),
+            uptime_ms: self.started_at.elapsed().as_millis() as u64,
+            model_path: IpcString::new(self.models.path(&profile)),
+            model_size: IpcString::new(&profile.model_size),
+            language: IpcString::new(&profile.language),
+            backend: IpcString::new(BACKEND),
+            ptt_key: IpcString::new(&self.config.ptt.key),
+            profile: IpcString::new(if recording { &profile.name } else { "" }),
+            output: IpcString::new(self.sinks.name(profile.output)),
+            vad: self.config.vad.enabled,
+        }
+    }
+
+    pub fn emit(&self, kind: DaemonEventKind, text: &str) {
+        self.publish(DaemonEvent::new(kind, text));
+    }
+
+    fn publish(&self, event: DaemonEvent) {
+        if let Err(e) = self.events.send_copy(event) {
+            error!("Failed to publish {} event: {}", event.kind.as_str(), e);
+        }
+    }
+
+    pub fn config_file_changed(&self) -> bool {
+        self.config_source.changed()
+    }
+
+    /// Re-reads the configuration and applies it in place. Only models that no
+    /// profile used before are loaded; on error the old settings stay.
+    pub fn reload_config(&mut self) -> Result<()> {
+        let config = self.config_source.load()?;
+        let ptt_bindings = parse_bindings(&config)?;
+        check_shortcuts(&config)?;
+        if self.is_recording() && config.profile(&self.profile.name).is_none() {
+            bail!(
+                "cannot remove profile `{}` while it is recording",
+                self.profile.name
+            );
+        }
+        let models = Models::load(&config, Some(&self.models))?;
+        let postprocessors = postprocessors(&config)?;
+        // Stopped recordings still type their final transcript, and finished
+        // ones can be undone.
+        let in_use: Vec<Output> = outputs(&config)
+            .chain(
+                self.finishing
+                    .iter()
+                    .map(|finishing| finishing.profile.output),
+            )
+            .chain(self.dictations.iter().map(|dictation| dictation.output))
+            .collect();
+        let sinks = Sinks::open(in_use.iter().copied(), Some(&self.sinks))?;
+
+        if let Some(profile) = config.profile(&self.profile.name) {
+            self.profile = profile;
+        }
+        let previous = std::mem::replace(&mut self.models, models);
+        self.models.reuse(previous);
+        self.postprocessors = postprocessors;
+        let previous = std::mem::replace(&mut self.sinks, sinks);
+        self.sinks.reuse(in_use, previous);
+        self.vad
+            .configure(&config.vad, self.audio_source.sample_rate());
+        self.agreement.set_agreement(config.ptt.partial_agreement);
+        *self.shared.ptt_bindings.lock().unwrap() = ptt_bindings;
+        self.shared
+            .partial_interval_ms
+            .store(partial_interval_ms(&config), Ordering::SeqCst);
+        self.config = config;
+
+        info!(
+            "Configuration reloaded: PTT key {}, {} extra binding(s), language {}, max duration {}s, partial interval {}s",
+            self.config.ptt.key,
+            self.config.ptt.bindings.len(),
+            self.config.language,
+            self.config.ptt.max_duration,
+            self.config.ptt.partial_interval
+        );
+        Ok(())
+    }
+
+    fn key_delay(&self) -> Duration {
+        Duration::from_millis(self.config.key_delay_ms)
+    }
+
+    fn notify(&self, body: &str) {
+        if self.config.notifications {
+            notify("Vype", body);
+        }
+    }
+
+    /// Stops and transcribes the current recording once it has run for `max_duration`.
+    pub fn check_max_duration(&mut self) -> Result<()> {
+        let max_recording_duration = Duration::from_secs(self.config.ptt.max_duration);
+        if self.is_recording()
+            && self
+                .recording_started_at
+                .is_some_and(|t| t.elapsed() >= max_recording_duration)
+        {
+            info!("Max recording duration reached");
+            self.emit(DaemonEventKind::MaxDurationReached, "");
+            self.handle_control_msg(ControlMsg::Stop)?;
+        }
+        Ok(())
+    }
+
+    /// Stops a recording once `ptt.silence_stop` seconds pass without speech.
+    /// Hands-free recordings end on `vad.silence` instead.
+    pub fn check_silence(&mut self) -> Result<()> {
+        let silence_stop = self.config.ptt.silence_stop;
+        if !self.is_recording()
+            || silence_stop == 0.0
+            || matches!(self.vad_state, VadState::Recording { .. })
+        {
+            return Ok(());
+        }
+
+        let samples = self.audio_source.samples_since(self.silence_fed);
+        self.silence_fed += samples.len();
+        self.vad.feed(&samples);
+
+        let silence_secs =
+            self.vad.trailing_silence() as f64 / self.audio_source.sample_rate() as f64;
+        if silence_secs >= silence_stop {
+            info!("No speech for {}s, stopping recording", silence_stop);
+            self.emit(DaemonEventKind::SilenceTimeout, "");
+            self.handle_control_msg(ControlMsg::Stop)?;
+        }
+        Ok(())
+    }
+
+    /// Drives hands-free mode: listens for speech while idle, records once it
+    /// is heard and hands the utterance to `process_stop` after the trailing silence.
+    pub fn poll_vad(&mut self) -> Result<()> {
+        let fed = match self.vad_state {
+            VadState::Off => {
+                if !self.config.vad.enabled || self.is_recording() {
+                    return Ok(());
+                }
+                if let Err(e) = self.audio_source.start() {
+                    // Retried on the next config reload rather than on every poll.
+                    self.config.vad.enabled = false;
+                    return Err(e.context("hands-free mode disabled"));
+                }
+                self.vad.reset();
+                self.vad_state = VadState::Listening { fed: 0 };
+                info!("Listening for speech");
+                0
+            }
+            VadState::Listening { .. } if !self.config.vad.enabled => {
+                self.audio_source.stop();
+                self.vad_state = VadState::Off;
+                info!("Hands-free mode disabled");
+                return Ok(());
+            }
+            VadState::Listening { fed } | VadState::Recording { fed } => fed,
+        };
+
+        let samples = self.audio_source.samples_since(fed);
+        let fed = fed + samples.len();
+        let event = self.vad.feed(&samples);
+        match self.vad_state {
+            VadState::Listening { .. } if event == Some(VadEvent::SpeechStarted) => {
+                self.begin_recording(default_profile(&self.config));
+                self.vad_state = VadState::Recording { fed };
+                info!("Recording started (speech detected)");
+            }
+            VadState::Listening { .. } => {
+                // Only keep what could become the start of the next utterance.
+                let pre_roll =
+                    (self.config.vad.pre_roll * self.audio_source.sample_rate() as f64) as usize;
+                let dropped = self
+                    .audio_source
+                    .trim_front(pre_roll + self.vad.start_delay());
+                self.vad_state = VadState::Listening {
+                    fed: fed - dropped.min(fed),
+                };
+            }
+            VadState::Recording { .. } if event == Some(VadEvent::SpeechEnded) => {
+                info!("Trailing silence detected");
+                self.stop_recording()?;
+            }
+            VadState::Recording { .. } => self.vad_state = VadState::Recording { fed },
+            VadState::Off => {}
+        }
+        Ok(())
+    }
+
+    /// Applies a control message and returns a short description of the outcome.
+    pub fn handle_control_msg(&mut self, msg: ControlMsg) -> Result<&'static str> {
+        let outcome = match msg {
+            ControlMsg::Start(profile) => {
+                if self.is_recording() {
+                    "Already recording"
+                } else {
+                    self.start_recording(&profile)?;
+                    info!("Recording started (profile {})", profile);
+                    "Recording started"
+                }
+            }
+            ControlMsg::Stop => {
+                if self.is_recording() {
+                    self.stop_recording()?;
+                    "Recording stopped"
+                } else {
+                    "Not recording"
+                }
+            }
+            ControlMsg::Cancel => {
+                if self.is_recording() {
+                    self.cancel_recording()?;
+                    "Recording cancelled"
+                } else {
+                    "Not recording"
+                }
+            }
+            ControlMsg::Undo => {
+                self.undo()?;
+                "Dictation undone"
+            }
+            ControlMsg::Retype(entry) => {
+                self.retype(entry)?;
+                "History entry typed"
+            }
+            ControlMsg::Partial => {
+                if self.is_recording() {
+                    self.process_partial()?;
+                    "Partial transcription queued"
+                } else {
+                    "Not recording"
+                }
+            }
+            ControlMsg::Toggle(profile) => {
+                if self.is_recording() {
+                    self.stop_recording()?;
+                    "Recording stopped"
+                } else {
+                    self.start_recording(&profile)?;
+                    info!("Recording started (toggle, profile {})", profile);
+                    "Recording started"
+                }
+            }
+            ControlMsg::Status => {
+                if self.is_recording() {
+                    "Recording"
+                } else {
+                    "Idle"
+                }
+            }
+            ControlMsg::Reload => {
+                self.reload_config()?;
+                "Configuration reloaded"
+            }
+        };
+
+        Ok(outcome)
+    }
+
+    fn start_recording(&mut self, profile: &str) -> Result<()> {
+        let Some(profile) = self.config.profile(profile) else {
+            bail!("unknown profile `{}`", profile);
+        };
+        // Restarting the stream takes it over from hands-free listening.
+        self.audio_source.start()?;
+        self.vad_state = VadState::Off;
+        self.vad.reset();
+        self.silence_fed = 0;
+        self.begin_recording(profile);
+        Ok(())
+    }
+
+    /// Marks the audio source, which must already be running, as recording.
+    fn begin_recording(&mut self, profile: Profile) {
+        self.profile = profile;
+        self.recording += 1;
+        self.shared.is_recording.store(true, Ordering::SeqCst);
+        self.recording_started_at = Some(Instant::now());
+        self.typing_state.clear();
+        self.agreement.reset();
+        self.stream.reset();
+        self.context = self.previous_text();
+        self.paste_shortcut = self.paste_shortcut();
+        self.emit(DaemonEventKind::RecordingStarted, "");
+        self.notify("Recording started");
+    }
+
+    /// The shortcut to paste with if the focused application gets the
+    /// transcript through the clipboard.
+    fn paste_shortcut(&self) -> Option<Shortcut> {
+        // Looking up the focused window is only worth it with per-app rules.
+        let app = if self.config.paste.apps.is_empty() {
+            None
+        } else {
+            focused_window().and_then(|window| window.app)
+        };
+        match self.config.paste.mode_for(app.as_deref()) {
+            (InsertMode::Type, _) => None,
+            (InsertMode::Paste, shortcut) => {
+                info!(
+                    "Pasting into {} with {}",
+                    app.as_deref().unwrap_or("the focused window"),
+                    shortcut
+                );
+                Some(shortcut.parse().expect("shortcuts are checked on load"))
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) -> Result<()> {
+        self.shared.is_recording.store(false, Ordering::SeqCst);
+        // `process_stop` closes the stream; hands-free mode reopens it.
+        self.vad_state = VadState::Off;
+        self.recording_started_at = None;
+        self.process_stop()?;
+        self.notify("Recording stopped");
+        Ok(())
+    }
+
+    /// Ends the current recording without transcribing it.
+    fn cancel_recording(&mut self) -> Result<()> {
+        self.shared.is_recording.store(false, Ordering::SeqCst);
+        self.vad_state = VadState::Off;
+        self.recording_started_at = None;
+        let samples = self.audio_source.stop();
+        self.worker.abort_partial();
+        info!("Recording cancelled. Discarded samples: {}", samples.len());
+        self.emit(DaemonEventKind::RecordingCancelled, "");
+        self.notify("Recording cancelled");
+
+        if self.config.ptt.erase_on_cancel && !self.typing_state.typed().is_empty() {
+            let ops = self.typing_state.transition("");
+            let key_delay = self.key_delay();
+            self.sinks
+                .get(self.profile.output)
+                .execute_all(&ops, key_delay)?;
+        }
+        self.typing_state.clear();
+        Ok(())
+    }
+
+    /// The last `prompt_context` characters of the previous dictation, if it
+    /// went into the focused window.
+    fn previous_text(&self) -> String {
+        let chars = self.config.prompt_context;
+        let Some(dictation) = self.dictations.back() else {
+            return String::new();
+        };
+        if chars == 0 || focused_window() != dictation.window {
+            return String::new();
+        }
+        let text = dictation.text.trim();
+        let start = text
+            .char_indices()
+            .rev()
+            .nth(chars - 1)
+            .map_or(0, |(i, _)| i);
+        text[start..].to_string()
+    }
+
+    /// What Whisper is told came before the audio of the next pass: the
+    /// profile's prompt, the previous dictation and the finalized text.
+    fn prompt(&self) -> String {
+        let initial = self.profile.initial_prompt();
+        [initial.as_str(), &self.context, self.stream.prompt()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The part of a recording worth transcribing, with silence cut from
+    /// both ends when `ptt.trim_silence` is set.
+    fn speech(&self, samples: &[f32]) -> Range<usize> {
+        if !self.config.ptt.trim_silence {
+            return 0..samples.len();
+        }
+        let sample_rate = self.audio_source.sample_rate();
+        let margin = (self.config.vad.pre_roll * sample_rate as f64) as usize;
+        trim_silence(samples, sample_rate, self.config.vad.threshold, margin)
+    }
+
+    /// Hands the speech in `window`, which starts `window_start` samples into
+    /// the recording, to the transcription worker.
+    fn transcribe(
+        &mut self,
+        kind: JobKind,
+        window_start: usize,
+        window: &[f32],
+        prompt: &str,
+    ) -> Result<()> {
+        let window_range = window_start..window_start + window.len();
+        let speech = self.speech(window);
+        if speech.is_empty() {
+            return self.apply_transcription(Transcription {
+                kind,
+                recording: self.recording,
+                window: window_range,
+                segments: Ok(Vec::new()),
+                discarded: Vec::new(),
+            });
+        }
+
+        let sample_rate = self.audio_source.sample_rate();
+        self.worker.submit(Job {
+            kind,
+            recording: self.recording,
+            window: window_range,
+            offset: speech.start as f64 / sample_rate as f64,
+            audio: window[speech].to_vec(),
+            sample_rate,
+            transcriber: self.models.transcriber(&self.profile),
+            profile: self.profile.clone(),
+            prompt: prompt.to_string(),
+            filter: self.config.filter.clone(),
+        });
+        Ok(())
+    }
+
+    /// Types the transcriptions the worker has finished since the last poll.
+    pub fn poll_transcriptions(&mut self) -> Result<()> {
+        while let Some(transcription) = self.worker.poll() {
+            self.apply_transcription(transcription)?;
+        }
+        Ok(())
+    }
+
+    /// Puts back the clipboard text a paste replaced, once it is due.
+    pub fn poll_clipboard(&mut self) {
+        self.paster.restore_due();
+    }
+
+    fn apply_transcription(&mut self, transcription: Transcription) -> Result<()> {
+        match transcription.kind {
+            JobKind::Partial => self.apply_partial(transcription),
+            JobKind::Final => self.apply_final(transcription),
+        }
+    }
+
+    fn process_stop(&mut self) -> Result<()> {
+        let samples = self.audio_source.stop();
+        info!("Recording stopped. Samples: {}", samples.len());
+        self.emit(DaemonEventKind::RecordingStopped, "");
+
+        // Partial transcriptions already finalized the audio before the window.
+        let sample_rate = self.audio_source.sample_rate();
+        let window_start = self.stream.window_start(sample_rate).min(samples.len());
+        let prompt = self.prompt();
+        // A new recording may start before the final transcript is in.
+        self.finishing.push_back(Finishing {
+            recording: self.recording,
+            profile: self.profile.clone(),
+            model: self.models.path(&self.profile).to_string(),
+            duration: samples.len() as f64 / sample_rate as f64,
+            audio: self.config.recordings.enabled.then(|| samples.clone()),
+            typing_state: std::mem::take(&mut self.typing_state),
+            stream: std::mem::take(&mut self.stream),
+            postprocessor: self.postprocessors[&self.profile.name].clone(),
+            paste_shortcut: self.paste_shortcut.take(),
+        });
+        self.transcribe(
+            JobKind::Final,
+            window_start,
+            &samples[window_start..],
+            &prompt,
+        )
+    }
+
+    fn apply_final(&mut self, transcription: Transcription) -> Result<()> {
+        let Some(index) = self
+            .finishing
+            .iter()
+            .position(|finishing| finishing.recording == transcription.recording)
+        else {
+            return Ok(());
+        };
+        let mut finishing = self.finishing.remove(index).unwrap();
+
+        let sample_rate = self.audio_source.sample_rate();
+        let text = transcription
+            .segments
+            .map(|segments| finishing.stream.finish(&segments, sample_rate));
+        if let Some(audio) = finishing.audio.take() {
+            self.save_recording(audio, &finishing, &text);
+        }
+
+        match text {
+            Ok(text) => {
+                let text = finishing.postprocessor.process(&text);
+                if text.is_empty() {
+                    if !transcription.discarded.is_empty() {
+                        let discarded: String = transcription
+                            .discarded
+                            .iter()
+                            .map(|segment| segment.text.as_str())
+                            .collect();
+                        let discarded = discarded.trim();
+                        info!("Discarded likely hallucination: {}", discarded);
+                        self.emit(DaemonEventKind::TranscriptDiscarded, discarded);
+                        self.notify(&format!("Discarded \"{}\"", discarded));
+                    } else if !transcription.window.is_empty() {
+                        info!("No speech detected");
+                    }
+                    return Ok(());
+                }
+
+                info!("Transcribed: {}", text);
+                self.emit(DaemonEventKind::FinalText, &text);
+                let key_delay = self.key_delay();
+                let sink = self.sinks.get(finishing.profile.output);
+                if let Some(shortcut) = &finishing.paste_shortcut {
+                    let restore_delay = self.config.paste.restore_delay;
+                    self.paster.paste(sink, &text, shortcut, restore_delay)?;
+                } else {
+                    let ops = finishing.typing_state.transition(&text);
+                    sink.execute_all(&ops, key_delay)?;
+                }
+
+                let window = focused_window();
+                self.log_history(HistoryEntry {
+                    timestamp: unix_timestamp_ms(),
+                    duration: finishing.duration,
+                    profile: finishing.profile.name,
+                    model: finishing.model,
+                    language: finishing.profile.language,
+                    text: text.clone(),
+                    window: window.as_ref().and_then(|window| window.app.clone()),
+                });
+                self.remember(text, finishing.profile.output, window);
+            }
+            Err(e) => {
+                error!("Transcription error: {}", e);
+                self.emit(DaemonEventKind::Error, &e.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save_recording(&self, audio: Vec<f32>, finishing: &Finishing, text: &Result<String>) {
+        let (text, error) = match text {
+            Ok(text) => (text.clone(), None),
+            Err(e) => (String::new(), Some(format!("{:#}", e))),
+        };
+        let sidecar = Sidecar {
+            timestamp: unix_timestamp_ms(),
+            duration: finishing.duration,
+            profile: finishing.profile.name.clone(),
+            model: finishing.model.clone(),
+            language: finishing.profile.language.clone(),
+            translate: finishing.profile.translate,
+            trim_silence: self.config.ptt.trim_silence,
+            text,
+            error,
+        };
+        save_recording(
+            &self.config.recordings,
+            audio,
+            self.audio_source.sample_rate(),
+            sidecar,
+        );
+    }
+
+    fn log_history(&self, entry: HistoryEntry) {
+        if !self.config.history {
+            return;
+        }
+        let Some(path) = history_path() else {
+            warn!("Could not determine the data directory, transcript not saved");
+            return;
+        };
+        if let Err(e) = append_history(&path, &entry) {
+            error!("Failed to write history to {}: {}", path.display(), e);
+        }
+    }
+
+    fn remember(&mut self, text: String, output: Output, window: Option<Window>) {
+        if self.dictations.len() == UNDO_HISTORY {
+            self.dictations.pop_front();
+        }
+        self.dictations.push_back(Dictation {
+            text,
+            output,
+            window,
+        });
+    }
+
+    /// Types the text of a history entry into the focused window, the way
+    /// the default profile would type a transcript.
+    fn retype(&mut self, number: usize) -> Result<()> {
+        if self.is_recording() {
+            bail!("cannot retype while recording");
+        }
+        let path = history_path().context("could not determine the data directory")?;
+        let entries =
+            read_history(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let Some((_, entry)) = entries.into_iter().find(|(n, _)| *n == number) else {
+            bail!("no history entry {}", number);
+        };
+
+        let output = default_profile(&self.config).output;
+        let paste_shortcut = self.paste_shortcut();
+        let key_delay = self.key_delay();
+        let sink = self.sinks.get(output);
+        if let Some(shortcut) = &paste_shortcut {
+            let restore_delay = self.config.paste.restore_delay;
+            self.paster
+                .paste(sink, &entry.text, shortcut, restore_delay)?;
+        } else {
+            sink.execute_all(&[KeyOp::Type(entry.text.clone())], key_delay)?;
+        }
+        info!("Retyped history entry {}: {}", number, entry.text);
+        self.remember(entry.text, output, focused_window());
+        Ok(())
+    }
+
+    /// Backspaces over the last dictation, provided the window it went into
+    /// still has focus. Text typed by hand after it would be erased instead.
+    fn undo(&mut self) -> Result<()> {
+        if self.is_recording() {
+            bail!("cannot undo while recording");
+        }
+        let Some(dictation) = self.dictations.back() else {
+            bail!("nothing to undo");
+        };
+        // Without knowing both windows the backspaces could land anywhere.
+        match (focused_window(), &dictation.window) {
+            (Some(focused), Some(window)) if focused == *window => {}
+            (Some(_), Some(_)) => bail!("the window of the last dictation no longer has focus"),
+            _ => bail!("cannot verify the focused window"),
+        }
+
+        let dictation = self.dictations.pop_back().unwrap();
+        let ops = [KeyOp::Backspace(dictation.text.chars().count())];
+        let key_delay = self.key_delay();
+        self.sinks
+            .get(dictation.output)
+            .execute_all(&ops, key_delay)?;
+        info!("Undid dictation: {}", dictation.text);
+        self.emit(DaemonEventKind::DictationUndone, &dictation.text);
+        Ok(())
+    }
+
+    /// Queues a transcription of the audio after the finalized part of the
+    /// streaming transcript.
+    fn process_partial(&mut self) -> Result<()> {
+        let sample_rate = self.audio_source.sample_rate();
+        let window_start = self.stream.window_start(sample_rate);
+        let window = self.audio_source.samples_since(window_start);
+        let prompt = self.prompt();
+        self.transcribe(JobKind::Partial, window_start, &window, &prompt)
+    }
+
+    /// Types the words the partial transcriptions agree on.
+    fn apply_partial(&mut self, transcription: Transcription) -> Result<()> {
+        // The recording this pass belongs to may have stopped since.
+        if transcription.recording != self.recording || !self.is_recording() {
+            return Ok(());
+        }
+
+        match transcription.segments {
+            Ok(segments) => {
+                let sample_rate = self.audio_source.sample_rate();
+                let text = self.stream.update(
+                    &segments,
+                    transcription.window.start,
+                    transcription.window.len(),
+                    sample_rate,
+                );
+                if !text.is_empty() {
+                    info!("Partial transcription: {}", text);
+                    let committed = self.agreement.update(&text);
+                    let committed = self.postprocessors[&self.profile.name].process(committed);
+                    let shown = format!("{}{}", committed, self.agreement.tentative());
+                    self.publish(DaemonEvent::partial(
+                        DaemonEventKind::PartialText,
+                        &shown,
+                        committed.len(),
+                    ));
+                    // Pasted transcripts only go in once, when the recording stops.
+                    if self.paste_shortcut.is_none() {
+                        let ops = self.typing_state.transition(&committed);
+                        let key_delay = self.key_delay();
+                        self.sinks
+                            .get(self.profile.output)
+                            .execute_all(&ops, key_delay)?;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Partial transcription error: {}", e);
+                self.emit(DaemonEventKind::Error, &e.to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use log::debug;
+use vype_shared::FilterConfig;
+
+use crate::transcriber::Segment;
+
+/// Lowercase words of `text`, without punctuation, for comparing phrases.
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `text` only describes sounds, like `[BLANK_AUDIO]` or `(music)`.
+fn is_annotation(text: &str) -> bool {
+    let text = text.trim();
+    [('[', ']'), ('(', ')'), ('*', '*'), ('♪', '♪')]
+        .iter()
+        .any(|&(open, close)| text.len() > 1 && text.starts_with(open) && text.ends_with(close))
+}
+
+/// Splits `segments` into the ones to keep and the ones Whisper likely made
+/// up. `level` is the peak level of the audio they were transcribed from,
+/// and `whole` is set when that audio is a recording from its start, so that
+/// a lone phantom phrase is the entire transcript.
+pub fn filter_segments(
+    segments: Vec<Segment>,
+    level: f32,
+    whole: bool,
+    config: &FilterConfig,
+) -> (Vec<Segment>, Vec<Segment>) {
+    if !config.enabled || segments.is_empty() {
+        return (segments, Vec::new());
+    }
+    if level < config.min_level {
+        debug!("Discarding transcript of audio at level {:.4}", level);
+        return (Vec::new(), segments);
+    }
+
+    let (kept, mut discarded): (Vec<_>, Vec<_>) = segments.into_iter().partition(|segment| {
+        let silent = segment.no_speech_prob > config.no_speech_prob
+            && segment.avg_logprob < config.min_logprob;
+        !silent && !is_annotation(&segment.text)
+    });
+
+    // A phrase said clearly is real speech; only doubt it when Whisper does.
+    let doubtful = kept.iter().any(|segment| {
+        segment.no_speech_prob > config.no_speech_prob || segment.avg_logprob < config.min_logprob
+    });
+    let text: String = kept.iter().map(|segment| segment.text.as_str()).collect();
+    let text = normalize(&text);
+    let phantom = whole
+        && doubtful
+        && !text.is_empty()
+        && config
+            .phrases
+            .iter()
+            .any(|phrase| normalize(phrase) == text);
+    if phantom {
+        discarded.extend(kept);
+        discarded.sort_by(|a, b| a.start.total_cmp(&b.start));
+        return (Vec::new(), discarded);
+    }
+    (kept, discarded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, no_speech_prob: f32, avg_logprob: f32) -> Segment {
+        Segment {
+            text: text.to_string(),
+            no_speech_prob,
+            avg_logprob,
+            ..Default::default()
+        }
+    }
+
+    fn texts(segments: &[Segment]) -> Vec<&str> {
+        segments
+            .iter()
+            .map(|segment| segment.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_segments() {
+        let config = FilterConfig::default();
+        let cases: &[(&[Segment], f32, bool, &[&str])] = &[
+            (
+                &[segment(" Hello there.", 0.1, -0.3)],
+                0.2,
+                true,
+                &[" Hello there."],
+            ),
+            // Too quiet to hold speech.
+            (&[segment(" Hello there.", 0.1, -0.3)], 0.001, true, &[]),
+            (&[segment(" [BLANK_AUDIO]", 0.1, -0.3)], 0.2, true, &[]),
+            (&[segment(" (upbeat music)", 0.1, -0.3)], 0.2, true, &[]),
+            // Likely silence, and not confident about the words.
+            (&[segment(" Hmm.", 0.9, -1.5)], 0.2, true, &[]),
+            (&[segment(" Hmm.", 0.9, -0.2)], 0.2, true, &[" Hmm."]),
+            // A phantom phrase needs Whisper to doubt it as well.
+            (
+                &[segment(" Thank you.", 0.1, -0.3)],
+                0.2,
+                true,
+                &[" Thank you."],
+            ),
+            (&[segment(" Thank you.", 0.7, -0.3)], 0.2, true, &[]),
+            (&[segment(" THANK YOU!", 0.1, -1.2)], 0.2, true, &[]),
+            // A real "you" is speech, not a phantom phrase.
+            (&[segment(" you", 0.1, -0.3)], 0.2, true, &[" you"]),
+            // Only the whole recording is compared with the phrases.
+            (
+                &[segment(" Thank you.", 0.7, -0.3)],
+                0.2,
+                false,
+                &[" Thank you."],
+            ),
+            (
+                &[
+                    segment(" Ship it.", 0.1, -0.3),
+                    segment(" Thank you.", 0.7, -0.3),
+                ],
+                0.2,
+                true,
+                &[" Ship it.", " Thank you."],
+            ),
+        ];
+        for (segments, level, whole, expected) in cases {
+            let (kept, discarded) = filter_segments(segments.to_vec(), *level, *whole, &config);
+            assert_eq!(texts(&kept), *expected, "{:?}", segments);
+            assert_eq!(kept.len() + discarded.len(), segments.len());
+        }
+
+        let config = FilterConfig {
+            enabled: false,
+            ..FilterConfig::default()
+        };
+        let (kept, _) = filter_segments(vec![segment(" you", 0.9, -2.0)], 0.0, true, &config);
+        assert_eq!(texts(&kept), [" you"]);
+    }
+}
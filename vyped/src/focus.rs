@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, event_created_child};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::{
+    self, ZwlrForeignToplevelHandleV1,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::{
+    self, ZwlrForeignToplevelManagerV1,
+};
+use x11rb::connection::Connection as _;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+/// The window with keyboard focus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Wayland app id or X11 window class.
+    pub app: Option<String>,
+    /// X11 window id. Wayland has no stable id, so windows of one app look alike.
+    id: Option<u32>,
+}
+
+/// App id and activation state of every toplevel the compositor announced.
+#[derive(Default)]
+struct Toplevels {
+    windows: HashMap<ObjectId, (Option<String>, bool)>,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Toplevels {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Toplevels {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        _: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(Toplevels, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Toplevels {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let window = state.windows.entry(handle.id()).or_default();
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => window.0 = Some(app_id),
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+                window.1 = state
+                    .chunks_exact(4)
+                    .any(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()) == activated);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The focused window, through the wlr foreign toplevel protocol.
+fn wayland_active_window() -> Result<Option<Window>> {
+    let connection = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<Toplevels>(&connection)?;
+    let manager: ZwlrForeignToplevelManagerV1 = globals
+        .bind(&queue.handle(), 1..=3, ())
+        .context("compositor does not support the foreign toplevel protocol")?;
+
+    let mut toplevels = Toplevels::default();
+    // The first roundtrip announces the toplevels, the second their state.
+    queue.roundtrip(&mut toplevels)?;
+    queue.roundtrip(&mut toplevels)?;
+    manager.stop();
+    connection.flush()?;
+
+    Ok(toplevels
+        .windows
+        .into_values()
+        .find(|(_, activated)| *activated)
+        .map(|(app, _)| Window { app, id: None }))
+}
+
+/// The `_NET_ACTIVE_WINDOW`, with the class from its `WM_CLASS` property.
+fn x11_active_window() -> Result<Option<Window>> {
+    let (connection, screen) = x11rb::connect(None)?;
+    let root = connection.setup().roots[screen].root;
+    let active_window = connection
+        .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+        .reply()?
+        .atom;
+
+    let reply = connection
+        .get_property(false, root, active_window, AtomEnum::WINDOW, 0, 1)?
+        .reply()?;
+    let Some(window) = reply.value32().and_then(|mut ids| ids.next()) else {
+        return Ok(None);
+    };
+    if window == 0 {
+        return Ok(None);
+    }
+
+    let reply = connection
+        .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?
+        .reply()?;
+    // WM_CLASS holds the instance name and then the class, both NUL-terminated.
+    let app = reply
+        .value
+        .split(|&byte| byte == 0)
+        .nth(1)
+        .filter(|class| !class.is_empty())
+        .map(|class| String::from_utf8_lossy(class).into_owned());
+    Ok(Some(Window {
+        app,
+        id: Some(window),
+    }))
+}
+
+/// The window with keyboard focus, as the Wayland compositor sees it, or as
+/// X11 (or XWayland) does when it cannot tell.
+pub fn active_window() -> Result<Option<Window>> {
+    if crate::sink::env_set("WAYLAND_DISPLAY") {
+        match wayland_active_window() {
+            Ok(window) => return Ok(window),
+            Err(e) if !crate::sink::env_set("DISPLAY") => return Err(e),
+            Err(_) => {}
+        }
+    }
+    x11_active_window()
+}
@@ -0,0 +1,484 @@
+use anyhow::{Result, bail};
+use rdev::{Button, EventType, Key};
+use std::str::FromStr;
+
+/// Every named `rdev::Key`, matched case-insensitively against its variant name.
+const KEYS: &[Key] = &[
+    Key::Alt,
+    Key::AltGr,
+    Key::Backspace,
+    Key::CapsLock,
+    Key::ControlLeft,
+    Key::ControlRight,
+    Key::Delete,
+    Key::DownArrow,
+    Key::End,
+    Key::Escape,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::Home,
+    Key::LeftArrow,
+    Key::MetaLeft,
+    Key::MetaRight,
+    Key::PageDown,
+    Key::PageUp,
+    Key::Return,
+    Key::RightArrow,
+    Key::ShiftLeft,
+    Key::ShiftRight,
+    Key::Space,
+    Key::Tab,
+    Key::UpArrow,
+    Key::PrintScreen,
+    Key::ScrollLock,
+    Key::Pause,
+    Key::NumLock,
+    Key::BackQuote,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::Num0,
+    Key::Minus,
+    Key::Equal,
+    Key::KeyQ,
+    Key::KeyW,
+    Key::KeyE,
+    Key::KeyR,
+    Key::KeyT,
+    Key::KeyY,
+    Key::KeyU,
+    Key::KeyI,
+    Key::KeyO,
+    Key::KeyP,
+    Key::LeftBracket,
+    Key::RightBracket,
+    Key::KeyA,
+    Key::KeyS,
+    Key::KeyD,
+    Key::KeyF,
+    Key::KeyG,
+    Key::KeyH,
+    Key::KeyJ,
+    Key::KeyK,
+    Key::KeyL,
+    Key::SemiColon,
+    Key::Quote,
+    Key::BackSlash,
+    Key::IntlBackslash,
+    Key::KeyZ,
+    Key::KeyX,
+    Key::KeyC,
+    Key::KeyV,
+    Key::KeyB,
+    Key::KeyN,
+    Key::KeyM,
+    Key::Comma,
+    Key::Dot,
+    Key::Slash,
+    Key::Insert,
+    Key::KpReturn,
+    Key::KpMinus,
+    Key::KpPlus,
+    Key::KpMultiply,
+    Key::KpDivide,
+    Key::Kp0,
+    Key::Kp1,
+    Key::Kp2,
+    Key::Kp3,
+    Key::Kp4,
+    Key::Kp5,
+    Key::Kp6,
+    Key::Kp7,
+    Key::Kp8,
+    Key::Kp9,
+    Key::KpDelete,
+    Key::Function,
+];
+
+/// Shorter or more familiar spellings accepted in addition to the variant names.
+const KEY_ALIASES: &[(&str, Key)] = &[
+    ("ctrl", Key::ControlLeft),
+    ("control", Key::ControlLeft),
+    ("lctrl", Key::ControlLeft),
+    ("rctrl", Key::ControlRight),
+    ("shift", Key::ShiftLeft),
+    ("lshift", Key::ShiftLeft),
+    ("rshift", Key::ShiftRight),
+    ("meta", Key::MetaLeft),
+    ("super", Key::MetaLeft),
+    ("win", Key::MetaLeft),
+    ("ralt", Key::AltGr),
+    ("enter", Key::Return),
+    ("esc", Key::Escape),
+    ("del", Key::Delete),
+    ("ins", Key::Insert),
+    ("up", Key::UpArrow),
+    ("down", Key::DownArrow),
+    ("left", Key::LeftArrow),
+    ("right", Key::RightArrow),
+    ("pgup", Key::PageUp),
+    ("pgdn", Key::PageDown),
+    ("print", Key::PrintScreen),
+    ("scroll", Key::ScrollLock),
+    ("caps", Key::CapsLock),
+    ("fn", Key::Function),
+    ("kpenter", Key::KpReturn),
+    ("`", Key::BackQuote),
+    ("-", Key::Minus),
+    ("=", Key::Equal),
+    ("[", Key::LeftBracket),
+    ("]", Key::RightBracket),
+    (";", Key::SemiColon),
+    ("'", Key::Quote),
+    ("\\", Key::BackSlash),
+    (",", Key::Comma),
+    (".", Key::Dot),
+    ("/", Key::Slash),
+];
+
+const BUTTON_ALIASES: &[(&str, Button)] = &[
+    ("mouseleft", Button::Left),
+    ("mouseright", Button::Right),
+    ("mousemiddle", Button::Middle),
+    ("mouseback", Button::Unknown(8)),
+    ("mouseforward", Button::Unknown(9)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Meta,
+}
+
+impl Modifier {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "ctrl" | "control" => Some(Modifier::Ctrl),
+            "alt" => Some(Modifier::Alt),
+            "shift" => Some(Modifier::Shift),
+            "meta" | "super" | "win" => Some(Modifier::Meta),
+            _ => None,
+        }
+    }
+
+    fn matches(self, key: Key) -> bool {
+        match self {
+            Modifier::Ctrl => matches!(key, Key::ControlLeft | Key::ControlRight),
+            Modifier::Alt => matches!(key, Key::Alt | Key::AltGr),
+            Modifier::Shift => matches!(key, Key::ShiftLeft | Key::ShiftRight),
+            Modifier::Meta => matches!(key, Key::MetaLeft | Key::MetaRight),
+        }
+    }
+}
+
+/// The key or mouse button that is held down to record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Key(Key),
+    Button(Button),
+}
+
+impl Trigger {
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(key) = KEYS
+            .iter()
+            .find(|key| format!("{:?}", key).to_lowercase() == name)
+        {
+            return Some(Trigger::Key(*key));
+        }
+
+        let short_name = if name.len() == 1 {
+            match name.chars().next() {
+                Some(c) if c.is_ascii_lowercase() => Some(format!("key{}", c)),
+                Some(c) if c.is_ascii_digit() => Some(format!("num{}", c)),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        if let Some(short_name) = short_name {
+            return Self::parse(&short_name);
+        }
+
+        if let Some((_, key)) = KEY_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            return Some(Trigger::Key(*key));
+        }
+        if let Some((_, button)) = BUTTON_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            return Some(Trigger::Button(*button));
+        }
+
+        // Raw codes for keys and buttons rdev has no name for, e.g. `Unknown(172)` or `Mouse10`.
+        if let Some(code) = name
+            .strip_prefix("unknown(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return code
+                .parse()
+                .ok()
+                .map(|code| Trigger::Key(Key::Unknown(code)));
+        }
+        if let Some(code) = name.strip_prefix("mouse") {
+            return code
+                .parse::<u8>()
+                .ok()
+                .filter(|code| *code >= 8)
+                .map(|code| Trigger::Button(Button::Unknown(code)));
+        }
+
+        None
+    }
+}
+
+/// A push-to-talk binding such as `F9`, `Pause`, `MouseBack` or `Ctrl+Alt+Space`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: Vec<Modifier>,
+    pub trigger: Trigger,
+}
+
+impl FromStr for Hotkey {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let parts: Vec<String> = spec
+            .split('+')
+            .map(|part| part.trim().to_lowercase())
+            .collect();
+        let Some((trigger, modifiers)) = parts.split_last() else {
+            bail!("empty key binding");
+        };
+
+        let mut parsed_modifiers = Vec::new();
+        for name in modifiers {
+            if name.is_empty() {
+                bail!("empty key name in `{}`", spec);
+            }
+            let Some(modifier) = Modifier::parse(name) else {
+                bail!(
+                    "`{}` in `{}` is not a modifier (expected Ctrl, Alt, Shift or Meta)",
+                    name,
+                    spec
+                );
+            };
+            if !parsed_modifiers.contains(&modifier) {
+                parsed_modifiers.push(modifier);
+            }
+        }
+
+        if trigger.is_empty() {
+            bail!("empty key name in `{}`", spec);
+        }
+        let Some(parsed_trigger) = Trigger::parse(trigger) else {
+            bail!("unknown key `{}` in `{}`", trigger, spec);
+        };
+
+        Ok(Self {
+            modifiers: parsed_modifiers,
+            trigger: parsed_trigger,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordEvent {
+    /// The binding at this index in the list passed to [`ChordTracker::handle`] fired.
+    Pressed(usize),
+    Released,
+}
+
+/// A push-to-talk key and the profile it records with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PttBinding {
+    pub hotkey: Hotkey,
+    pub profile: String,
+}
+
+/// Follows keyboard and mouse events to report when one of the bound
+/// [`Hotkey`] chords is pressed and when any part of it is released again.
+#[derive(Debug, Default)]
+pub struct ChordTracker {
+    held_modifiers: Vec<Key>,
+    /// Binding that fired, kept so a reload while it is held still releases it.
+    active: Option<Hotkey>,
+}
+
+impl ChordTracker {
+    pub fn handle(&mut self, bindings: &[PttBinding], event: &EventType) -> Option<ChordEvent> {
+        match event {
+            EventType::KeyPress(key) => {
+                if is_modifier_key(*key) && !self.held_modifiers.contains(key) {
+                    self.held_modifiers.push(*key);
+                }
+                self.press(bindings, Trigger::Key(*key))
+            }
+            EventType::KeyRelease(key) => {
+                self.held_modifiers.retain(|held| held != key);
+                self.release(Trigger::Key(*key))
+            }
+            EventType::ButtonPress(button) => self.press(bindings, Trigger::Button(*button)),
+            EventType::ButtonRelease(button) => self.release(Trigger::Button(*button)),
+            _ => None,
+        }
+    }
+
+    fn modifier_held(&self, modifier: Modifier) -> bool {
+        self.held_modifiers.iter().any(|key| modifier.matches(*key))
+    }
+
+    fn press(&mut self, bindings: &[PttBinding], trigger: Trigger) -> Option<ChordEvent> {
+        if self.active.is_some() {
+            return None;
+        }
+        // With both `F9` and `Ctrl+F9` bound, holding Ctrl picks the latter.
+        let (index, binding) = bindings
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.hotkey.trigger == trigger)
+            .filter(|(_, b)| b.hotkey.modifiers.iter().all(|m| self.modifier_held(*m)))
+            .min_by_key(|(_, b)| std::cmp::Reverse(b.hotkey.modifiers.len()))?;
+        self.active = Some(binding.hotkey.clone());
+        Some(ChordEvent::Pressed(index))
+    }
+
+    fn release(&mut self, trigger: Trigger) -> Option<ChordEvent> {
+        let active = self.active.as_ref()?;
+        let released =
+            trigger == active.trigger || active.modifiers.iter().any(|m| !self.modifier_held(*m));
+        if released {
+            self.active = None;
+            Some(ChordEvent::Released)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_modifier_key(key: Key) -> bool {
+    [
+        Modifier::Ctrl,
+        Modifier::Alt,
+        Modifier::Shift,
+        Modifier::Meta,
+    ]
+    .iter()
+    .any(|m| m.matches(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hotkey(spec: &str) -> Hotkey {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_hotkey() {
+        assert_eq!(hotkey("F9").trigger, Trigger::Key(Key::F9));
+        assert_eq!(hotkey("pause").trigger, Trigger::Key(Key::Pause));
+        assert_eq!(hotkey("ScrollLock").trigger, Trigger::Key(Key::ScrollLock));
+        assert_eq!(hotkey("x").trigger, Trigger::Key(Key::KeyX));
+        assert_eq!(
+            hotkey("MouseBack").trigger,
+            Trigger::Button(Button::Unknown(8))
+        );
+        assert_eq!(
+            hotkey("Unknown(172)").trigger,
+            Trigger::Key(Key::Unknown(172))
+        );
+
+        let chord = hotkey("Ctrl+Alt+Space");
+        assert_eq!(chord.modifiers, vec![Modifier::Ctrl, Modifier::Alt]);
+        assert_eq!(chord.trigger, Trigger::Key(Key::Space));
+
+        assert!("F13".parse::<Hotkey>().is_err());
+        assert!("Ctrl+".parse::<Hotkey>().is_err());
+        assert!("Space+F9".parse::<Hotkey>().is_err());
+    }
+
+    fn binding(spec: &str, profile: &str) -> PttBinding {
+        PttBinding {
+            hotkey: hotkey(spec),
+            profile: profile.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_chord_tracker() {
+        let chord = [binding("Ctrl+Space", "default")];
+        let mut tracker = ChordTracker::default();
+
+        assert_eq!(
+            tracker.handle(&chord, &EventType::KeyPress(Key::Space)),
+            None
+        );
+        assert_eq!(
+            tracker.handle(&chord, &EventType::KeyRelease(Key::Space)),
+            None
+        );
+
+        tracker.handle(&chord, &EventType::KeyPress(Key::ControlRight));
+        assert_eq!(
+            tracker.handle(&chord, &EventType::KeyPress(Key::Space)),
+            Some(ChordEvent::Pressed(0))
+        );
+        // Auto-repeat does not press again.
+        assert_eq!(
+            tracker.handle(&chord, &EventType::KeyPress(Key::Space)),
+            None
+        );
+        assert_eq!(
+            tracker.handle(&chord, &EventType::KeyRelease(Key::ControlRight)),
+            Some(ChordEvent::Released)
+        );
+        assert_eq!(
+            tracker.handle(&chord, &EventType::KeyRelease(Key::Space)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_chord_tracker_prefers_most_specific_binding() {
+        let bindings = [
+            binding("F9", "default"),
+            binding("Ctrl+F9", "spanish"),
+            binding("F10", "spanish"),
+        ];
+        let mut tracker = ChordTracker::default();
+
+        assert_eq!(
+            tracker.handle(&bindings, &EventType::KeyPress(Key::F9)),
+            Some(ChordEvent::Pressed(0))
+        );
+        tracker.handle(&bindings, &EventType::KeyRelease(Key::F9));
+
+        tracker.handle(&bindings, &EventType::KeyPress(Key::ControlLeft));
+        assert_eq!(
+            tracker.handle(&bindings, &EventType::KeyPress(Key::F9)),
+            Some(ChordEvent::Pressed(1))
+        );
+        // Another binding does not fire while one is held.
+        assert_eq!(
+            tracker.handle(&bindings, &EventType::KeyPress(Key::F10)),
+            None
+        );
+    }
+}
@@ -1,25 +1,37 @@
 use anyhow::Result;
 use clap::Parser;
+use clap::builder::PossibleValuesParser;
 use iceoryx2::prelude::*;
-use libxdo::XDo;
 use log::{error, info};
-use rdev::{EventType, Key, listen};
+use rdev::listen;
+use signal_hook::consts::SIGHUP;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
-use std::time::Instant;
-use vype_shared::{AppConfig, PttConfig, PttEvent, PttEventType};
-use vype_shared::{KeyOp, TypingState};
+use std::time::{Duration, Instant};
+use vype_shared::{DaemonEvent, DaemonEventKind, MODEL_SIZES, OUTPUTS, PttEvent, PttResponse};
 
 mod audio;
+mod config;
+mod daemon;
+mod filter;
+mod focus;
+mod hotkey;
 mod model;
+mod offline;
+mod paste;
+mod recordings;
 mod resample;
+mod sink;
+mod stream;
 mod transcriber;
+mod vad;
+mod worker;
 
-use audio::CpalAudioSource;
-use model::get_model_path;
-use resample::resample_to_16khz_mono;
-use transcriber::WhisperTranscriber;
+use config::{ConfigOverrides, ConfigSource};
+use daemon::{ControlMsg, Daemon, SharedState};
+use hotkey::{ChordEvent, ChordTracker};
+use offline::TranscribeArgs;
 
 #[cfg(all(feature = "cpu", any(feature = "cuda", feature = "vulkan")))]
 compile_error!(
@@ -34,256 +46,61 @@ compile_error!(
 #[cfg(not(any(feature = "cpu", feature = "vulkan", feature = "cuda")))]
 compile_error!("One transcription backend is required. Enable one of: cpu, vulkan, cuda.");
 
+#[cfg(feature = "cpu")]
+const BACKEND: &str = "cpu";
+#[cfg(feature = "vulkan")]
+const BACKEND: &str = "vulkan";
+#[cfg(feature = "cuda")]
+const BACKEND: &str = "cuda";
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Parser, Debug)]
 #[command(name = "vyped")]
 #[command(about = "Vype daemon - Audio capture, transcription, and typing")]
 struct Args {
-    #[arg(short = 'm', long = "model", value_name = "PATH")]
-    model: Option<String>,
-
-    #[arg(short = 's', long = "model-size", default_value = "medium", value_name = "SIZE", value_parser = ["tiny", "base", "small", "medium", "large"])]
-    model_size: String,
-
-    #[arg(
-        short = 'l',
-        long = "language",
-        default_value = "en",
-        value_name = "LANG"
-    )]
-    language: String,
-
-    #[arg(short = 'k', long = "key", default_value = "F9", value_name = "KEY")]
-    key: String,
-
-    #[arg(
-        short = 'd',
-        long = "max-duration",
-        default_value = "30",
-        value_name = "SEC"
-    )]
-    max_duration: u64,
-
-    #[arg(
-        short = 'p',
-        long = "partial-interval",
-        default_value = "2.0",
-        value_name = "SECS"
-    )]
-    partial_interval: f64,
-
-    #[arg(short = 'v', long = "verbose")]
-    verbose: bool,
-}
-
-#[derive(Debug, Clone, Copy)]
-enum ControlMsg {
-    Start,
-    Stop,
-    Partial,
-    Toggle,
-}
-
-impl From<PttEventType> for ControlMsg {
-    fn from(value: PttEventType) -> Self {
-        match value {
-            PttEventType::StartRecording => ControlMsg::Start,
-            PttEventType::StopRecording => ControlMsg::Stop,
-            PttEventType::PartialTranscribe => ControlMsg::Partial,
-            PttEventType::ToggleRecording => ControlMsg::Toggle,
-        }
-    }
-}
-
-fn parse_ptt_key(key: &str) -> Key {
-    match key.to_uppercase().as_str() {
-        "F1" => Key::F1,
-        "F2" => Key::F2,
-        "F3" => Key::F3,
-        "F4" => Key::F4,
-        "F5" => Key::F5,
-        "F6" => Key::F6,
-        "F7" => Key::F7,
-        "F8" => Key::F8,
-        "F9" => Key::F9,
-        "F10" => Key::F10,
-        "F11" => Key::F11,
-        "F12" => Key::F12,
-        _ => Key::F9,
-    }
-}
+    #[arg(short = 'c', long = "config", value_name = "PATH")]
+    config: Option<PathBuf>,
 
-fn notify(title: &str, body: &str) {
-    if let Err(e) = notify_rust::Notification::new()
-        .summary(title)
-        .body(body)
-        .timeout(2000)
-        .show()
-    {
-        error!("Failed to show notification: {}", e);
-    }
-}
+    #[arg(long = "print-config")]
+    print_config: bool,
 
-fn execute_key_op(xdo: &XDo, op: &KeyOp) -> Result<()> {
-    match op {
-        KeyOp::Backspace(n) => {
-            for _ in 0..*n {
-                xdo.send_keysequence("BackSpace", 0)?;
-            }
-        }
-        KeyOp::Delete(n) => {
-            for _ in 0..*n {
-                xdo.send_keysequence("Delete", 0)?;
-            }
-        }
-        KeyOp::Type(c) => {
-            xdo.enter_text(&c.to_string(), 0)?;
-        }
-        KeyOp::Left(n) => {
-            for _ in 0..*n {
-                xdo.send_keysequence("Left", 0)?;
-            }
-        }
-        KeyOp::Right(n) => {
-            for _ in 0..*n {
-                xdo.send_keysequence("Right", 0)?;
-            }
-        }
-    }
-    Ok(())
-}
+    #[arg(short = 'm', long = "model", value_name = "PATH")]
+    model: Option<String>,
 
-fn execute_ops(xdo: &XDo, ops: &[KeyOp]) -> Result<()> {
-    for op in ops {
-        execute_key_op(xdo, op)?;
-    }
-    Ok(())
-}
+    #[arg(short = 's', long = "model-size", value_name = "SIZE", value_parser = PossibleValuesParser::new(MODEL_SIZES))]
+    model_size: Option<String>,
 
-fn process_stop(
-    typing_state: &mut TypingState,
-    audio_source: &mut CpalAudioSource,
-    transcriber: &WhisperTranscriber,
-    xdo: &XDo,
-) -> Result<()> {
-    let samples = audio_source.stop();
-    info!("Recording stopped. Samples: {}", samples.len());
-
-    if samples.is_empty() {
-        typing_state.clear();
-        return Ok(());
-    }
+    #[arg(short = 'l', long = "language", value_name = "LANG")]
+    language: Option<String>,
 
-    let resampled = resample_to_16khz_mono(
-        &samples,
-        audio_source.sample_rate(),
-        audio_source.channels(),
-    );
-    info!(
-        "Resampled from {}Hz to 16kHz: {} -> {} samples",
-        audio_source.sample_rate(),
-        samples.len(),
-        resampled.len()
-    );
+    #[arg(short = 'k', long = "key", value_name = "KEY")]
+    key: Option<String>,
 
-    match transcriber.transcribe(&resampled) {
-        Ok(text) => {
-            info!("Transcribed: {}", text);
-            if !text.is_empty() {
-                let ops = typing_state.transition(&text);
-                execute_ops(xdo, &ops)?;
-            }
-        }
-        Err(e) => {
-            error!("Transcription error: {}", e);
-            typing_state.clear();
-        }
-    }
+    #[arg(short = 'o', long = "output", value_name = "OUTPUT", value_parser = PossibleValuesParser::new(OUTPUTS))]
+    output: Option<String>,
 
-    Ok(())
-}
+    #[arg(short = 'd', long = "max-duration", value_name = "SEC")]
+    max_duration: Option<u64>,
 
-fn process_partial(
-    typing_state: &mut TypingState,
-    audio_source: &mut CpalAudioSource,
-    transcriber: &WhisperTranscriber,
-    xdo: &XDo,
-) -> Result<()> {
-    let samples = audio_source.get_current_samples();
-    if samples.is_empty() {
-        return Ok(());
-    }
+    #[arg(short = 'p', long = "partial-interval", value_name = "SECS")]
+    partial_interval: Option<f64>,
 
-    let resampled = resample_to_16khz_mono(
-        &samples,
-        audio_source.sample_rate(),
-        audio_source.channels(),
-    );
+    /// Hands-free mode: record whenever speech is detected
+    #[arg(long = "vad")]
+    vad: bool,
 
-    match transcriber.transcribe(&resampled) {
-        Ok(text) => {
-            if !text.is_empty() {
-                info!("Partial transcription: {}", text);
-                let ops = typing_state.transition(&text);
-                execute_ops(xdo, &ops)?;
-            }
-        }
-        Err(e) => error!("Partial transcription error: {}", e),
-    }
+    #[arg(short = 'v', long = "verbose")]
+    verbose: bool,
 
-    Ok(())
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn handle_control_msg(
-    msg: ControlMsg,
-    is_recording: &Arc<AtomicBool>,
-    recording_started_at: &mut Option<Instant>,
-    typing_state: &mut TypingState,
-    audio_source: &mut CpalAudioSource,
-    transcriber: &WhisperTranscriber,
-    xdo: &XDo,
-) -> Result<()> {
-    match msg {
-        ControlMsg::Start => {
-            if !is_recording.load(Ordering::SeqCst) {
-                is_recording.store(true, Ordering::SeqCst);
-                *recording_started_at = Some(Instant::now());
-                typing_state.clear();
-                audio_source.start()?;
-                info!("Recording started");
-                notify("Vype", "Recording started");
-            }
-        }
-        ControlMsg::Stop => {
-            if is_recording.load(Ordering::SeqCst) {
-                is_recording.store(false, Ordering::SeqCst);
-                *recording_started_at = None;
-                process_stop(typing_state, audio_source, transcriber, xdo)?;
-                notify("Vype", "Recording stopped");
-            }
-        }
-        ControlMsg::Partial => {
-            if is_recording.load(Ordering::SeqCst) {
-                process_partial(typing_state, audio_source, transcriber, xdo)?;
-            }
-        }
-        ControlMsg::Toggle => {
-            if is_recording.load(Ordering::SeqCst) {
-                is_recording.store(false, Ordering::SeqCst);
-                *recording_started_at = None;
-                process_stop(typing_state, audio_source, transcriber, xdo)?;
-                notify("Vype", "Recording stopped");
-            } else {
-                is_recording.store(true, Ordering::SeqCst);
-                *recording_started_at = Some(Instant::now());
-                typing_state.clear();
-                audio_source.start()?;
-                info!("Recording started (toggle)");
-                notify("Vype", "Recording started");
-            }
-        }
-    }
-
-    Ok(())
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Transcribe audio files instead of running the daemon
+    Transcribe(TranscribeArgs),
 }
 
 fn main() -> Result<()> {
@@ -297,21 +114,44 @@ fn main() -> Result<()> {
         })
         .init();
 
-    let config = AppConfig {
-        model: args.model,
-        model_size: args.model_size,
-        language: args.language,
-        ptt: PttConfig {
-            key: args.key,
+    let mut config_source = ConfigSource::new(
+        args.config.clone(),
+        ConfigOverrides {
+            model: args.model.clone(),
+            model_size: args.model_size.clone(),
+            language: args.language.clone(),
+            key: args.key.clone(),
+            output: args
+                .output
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(anyhow::Error::msg)?,
             max_duration: args.max_duration,
             partial_interval: args.partial_interval,
+            vad: args.vad.then_some(true),
         },
-    };
+    );
+    let config = config_source.load()?;
+
+    if args.print_config {
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
+    if let Some(Command::Transcribe(transcribe_args)) = &args.command {
+        return offline::transcribe_files(&config, transcribe_args);
+    }
 
     info!(
         "Starting vyped daemon with model size {} and PTT key {}",
         config.model_size, config.ptt.key
     );
+    for binding in &config.ptt.bindings {
+        info!(
+            "PTT key {} records with profile {}",
+            binding.key, binding.profile
+        );
+    }
     info!(
         "Max recording duration: {}s, partial interval: {}s",
         config.ptt.max_duration, config.ptt.partial_interval
@@ -319,72 +159,72 @@ fn main() -> Result<()> {
     info!("Press Ctrl+C to exit.");
 
     let running = Arc::new(AtomicBool::new(true));
-    let is_recording = Arc::new(AtomicBool::new(false));
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    let shared = SharedState::new(&config)?;
 
     let running_for_ctrlc = running.clone();
     ctrlc::set_handler(move || {
         running_for_ctrlc.store(false, Ordering::SeqCst);
     })?;
-
-    let (mut audio_source, transcriber, xdo) = {
-        let model_path = get_model_path(config.model.as_deref(), Some(&config.model_size))?;
-        let model_path_str = model_path
-            .to_str()
-            .ok_or_else(|| anyhow::anyhow!("model path contains invalid UTF-8"))?;
-        let audio_source = CpalAudioSource::new()?;
-        let transcriber = WhisperTranscriber::new(model_path_str, &config.language)?;
-        let xdo = XDo::new(None)?;
-        (audio_source, transcriber, xdo)
-    };
-
-    let mut typing_state = TypingState::new();
-    let mut recording_started_at: Option<Instant> = None;
-    let max_recording_duration = Duration::from_secs(config.ptt.max_duration);
+    signal_hook::flag::register(SIGHUP, reload_requested.clone())?;
 
     let node = NodeBuilder::new()
         .name(&"vyped".try_into()?)
         .create::<ipc::Service>()?;
 
-    let ptt_service = node
-        .service_builder(&"vype/ptt_events".try_into()?)
-        .publish_subscribe::<PttEvent>()
-        .max_publishers(8)
-        .max_subscribers(4)
+    let control_service = node
+        .service_builder(&"vype/control".try_into()?)
+        .request_response::<PttEvent, PttResponse>()
+        .max_clients(8)
+        .max_servers(1)
+        .open_or_create()?;
+
+    let control_server = control_service.server_builder().create()?;
+
+    let event_service = node
+        .service_builder(&"vype/events".try_into()?)
+        .publish_subscribe::<DaemonEvent>()
+        .max_publishers(1)
+        .max_subscribers(8)
+        .subscriber_max_buffer_size(32)
         .open_or_create()?;
 
-    let ptt_subscriber = ptt_service.subscriber_builder().create()?;
+    let event_publisher = event_service.publisher_builder().create()?;
+
+    let mut daemon = Daemon::new(config, config_source, shared.clone(), event_publisher)?;
 
     let (control_tx, control_rx) = std::sync::mpsc::channel::<ControlMsg>();
 
-    let key = parse_ptt_key(&config.ptt.key);
+    let ptt_bindings = shared.ptt_bindings.clone();
     let key_tx = control_tx.clone();
     std::thread::spawn(move || {
-        let key_down = Arc::new(AtomicBool::new(false));
-        let key_down_cb = key_down.clone();
-        if let Err(e) = listen(move |event| match event.event_type {
-            EventType::KeyPress(k) if k == key => {
-                if !key_down_cb.swap(true, Ordering::SeqCst) {
-                    let _ = key_tx.send(ControlMsg::Start);
+        let mut tracker = ChordTracker::default();
+        if let Err(e) = listen(move |event| {
+            let bindings = ptt_bindings.lock().unwrap();
+            match tracker.handle(&bindings, &event.event_type) {
+                Some(ChordEvent::Pressed(index)) => {
+                    let profile = bindings[index].profile.clone();
+                    let _ = key_tx.send(ControlMsg::Start(profile));
                 }
-            }
-            EventType::KeyRelease(k) if k == key => {
-                if key_down_cb.swap(false, Ordering::SeqCst) {
+                Some(ChordEvent::Released) => {
                     let _ = key_tx.send(ControlMsg::Stop);
                 }
+                None => {}
             }
-            _ => {}
         }) {
             error!("Keyboard listener failed: {:?}", e);
         }
     });
 
     let timer_tx = control_tx.clone();
-    let recording_for_timer = is_recording.clone();
+    let recording_for_timer = shared.is_recording.clone();
+    let partial_interval_ms = shared.partial_interval_ms.clone();
     let running_for_timer = running.clone();
-    let partial_interval = Duration::from_secs_f64(config.ptt.partial_interval);
     std::thread::spawn(move || {
         while running_for_timer.load(Ordering::SeqCst) {
-            std::thread::sleep(partial_interval);
+            std::thread::sleep(Duration::from_millis(
+                partial_interval_ms.load(Ordering::SeqCst),
+            ));
             if recording_for_timer.load(Ordering::SeqCst) {
                 let _ = timer_tx.send(ControlMsg::Partial);
             }
@@ -393,44 +233,63 @@ fn main() -> Result<()> {
 
     info!("Listening for PTT key and IPC control events...");
 
+    let mut last_config_check = Instant::now();
+
     while running.load(Ordering::SeqCst) {
-        while let Some(sample) = ptt_subscriber.receive()? {
-            let msg: ControlMsg = sample.event_type.into();
-            let _ = control_tx.send(msg);
+        while let Some(request) = control_server.receive()? {
+            let msg = ControlMsg::from(request.payload());
+            let response = match daemon.handle_control_msg(msg) {
+                Ok(outcome) => PttResponse::ok(daemon.status(), outcome),
+                Err(e) => {
+                    error!("Failed to process control request: {:#}", e);
+                    daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
+                    PttResponse::error(daemon.status(), &format!("{:#}", e))
+                }
+            };
+            if let Err(e) = request.send_copy(response) {
+                error!("Failed to send control response: {}", e);
+            }
         }
 
         while let Ok(msg) = control_rx.try_recv() {
-            if let Err(e) = handle_control_msg(
-                msg,
-                &is_recording,
-                &mut recording_started_at,
-                &mut typing_state,
-                &mut audio_source,
-                &transcriber,
-                &xdo,
-            ) {
+            if let Err(e) = daemon.handle_control_msg(msg) {
                 error!("Failed to process control message: {}", e);
+                daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
             }
         }
 
-        if is_recording.load(Ordering::SeqCst)
-            && recording_started_at
-                .as_ref()
-                .is_some_and(|t| t.elapsed() >= max_recording_duration)
-        {
-            if let Err(e) = handle_control_msg(
-                ControlMsg::Stop,
-                &is_recording,
-                &mut recording_started_at,
-                &mut typing_state,
-                &mut audio_source,
-                &transcriber,
-                &xdo,
-            ) {
-                error!("Failed to process max-duration stop: {}", e);
-            }
+        let mut reload = reload_requested.swap(false, Ordering::SeqCst);
+        if last_config_check.elapsed() >= CONFIG_POLL_INTERVAL {
+            last_config_check = Instant::now();
+            reload |= daemon.config_file_changed();
+        }
+        if reload && let Err(e) = daemon.handle_control_msg(ControlMsg::Reload) {
+            error!("Failed to reload configuration: {:#}", e);
+            daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
+        }
+
+        if let Err(e) = daemon.check_max_duration() {
+            error!("Failed to process max-duration stop: {}", e);
+            daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
+        }
+
+        if let Err(e) = daemon.check_silence() {
+            error!("Failed to process silence stop: {:#}", e);
+            daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
         }
 
+        if let Err(e) = daemon.poll_transcriptions() {
+            error!("Failed to type transcription: {:#}", e);
+            daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
+        }
+
+        if let Err(e) = daemon.poll_vad() {
+            error!("Hands-free mode failed: {:#}", e);
+            daemon.emit(DaemonEventKind::Error, &format!("{:#}", e));
+        }
+
+        daemon.poll_clipboard();
+
         std::thread::sleep(Duration::from_millis(1));
     }
 
@@ -1,16 +1,21 @@
 use anyhow::Result;
 use hf_hub::api::sync::Api;
 use std::path::PathBuf;
+use vype_shared::Profile;
 
 const DEFAULT_MODEL_SIZE: &str = "medium";
 
-pub fn get_model_path(custom_path: Option<&str>, model_size: Option<&str>) -> Result<PathBuf> {
+pub fn get_model_path(
+    custom_path: Option<&str>,
+    model_size: Option<&str>,
+    multilingual: bool,
+) -> Result<PathBuf> {
     if let Some(path) = custom_path {
         return Ok(PathBuf::from(path));
     }
 
     let size = model_size.unwrap_or(DEFAULT_MODEL_SIZE);
-    let model_filename = model_filename_for_size(size);
+    let model_filename = model_filename_for_size(size, multilingual);
 
     let config_dir = dirs::config_dir()
         .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
@@ -36,13 +41,72 @@ pub fn get_model_path(custom_path: Option<&str>, model_size: Option<&str>) -> Re
     Ok(model_path)
 }
 
-fn model_filename_for_size(size: &str) -> String {
-    match size {
-        "tiny" => "ggml-tiny.en.bin".to_string(),
-        "base" => "ggml-base.en.bin".to_string(),
-        "small" => "ggml-small.en.bin".to_string(),
-        "medium" => "ggml-medium.en.bin".to_string(),
-        "large" => "ggml-large-v3.bin".to_string(),
-        _ => "ggml-small.en.bin".to_string(),
+/// Whether `profile` needs a model that knows languages other than English.
+fn needs_multilingual(profile: &Profile) -> bool {
+    profile.language != "en" || profile.translate
+}
+
+/// Path of the model `profile` transcribes with, downloading it if needed.
+pub fn resolve_model_path(profile: &Profile) -> Result<String> {
+    let model_path = get_model_path(
+        profile.model.as_deref(),
+        Some(&profile.model_size),
+        needs_multilingual(profile),
+    )?;
+    Ok(model_path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("model path contains invalid UTF-8"))?
+        .to_string())
+}
+
+/// The whisper.cpp model file for `size`. The English-only `.en` models are
+/// a little better at English, but cannot transcribe anything else.
+fn model_filename_for_size(size: &str, multilingual: bool) -> String {
+    let size = match size {
+        "tiny" | "base" | "small" | "medium" => size,
+        "large" => return "ggml-large-v3.bin".to_string(),
+        _ => "small",
+    };
+    if multilingual {
+        format!("ggml-{}.bin", size)
+    } else {
+        format!("ggml-{}.en.bin", size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vype_shared::AppConfig;
+
+    fn model_filename(config: &AppConfig, profile: &str) -> String {
+        let profile = config.profile(profile).unwrap();
+        model_filename_for_size(&profile.model_size, needs_multilingual(&profile))
+    }
+
+    #[test]
+    fn test_model_filename() {
+        let config = AppConfig::from_toml(
+            r#"
+            model_size = "small"
+
+            [profiles.es]
+            language = "es"
+
+            [profiles.translate]
+            language = "de"
+            translate = true
+            model_size = "tiny"
+
+            [profiles.large]
+            model_size = "large"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(model_filename(&config, "default"), "ggml-small.en.bin");
+        assert_eq!(model_filename(&config, "es"), "ggml-small.bin");
+        assert_eq!(model_filename(&config, "translate"), "ggml-tiny.bin");
+        assert_eq!(model_filename(&config, "large"), "ggml-large-v3.bin");
     }
 }
@@ -0,0 +1,331 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use log::{error, info};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use vype_shared::{AppConfig, PostProcessor};
+
+use crate::filter::filter_segments;
+use crate::model::resolve_model_path;
+use crate::resample::resample_to_16khz_mono;
+use crate::transcriber::{Segment, TranscribeOptions, WhisperTranscriber};
+use crate::vad::peak_level;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Txt,
+    Json,
+    Srt,
+    Vtt,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Txt => "txt",
+            Format::Json => "json",
+            Format::Srt => "srt",
+            Format::Vtt => "vtt",
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TranscribeArgs {
+    /// Audio files (WAV, FLAC or Ogg Vorbis)
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = Format::Txt)]
+    format: Format,
+
+    /// Profile whose model and language to use (default: the default profile)
+    #[arg(short = 'p', long = "profile", value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Write `<name>.<format>` for each file here instead of printing to stdout
+    #[arg(long = "out-dir", value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+
+    /// Add the time and probability of each word to JSON output
+    #[arg(long = "words")]
+    words: bool,
+}
+
+#[derive(Serialize)]
+struct JsonTranscript<'a> {
+    file: &'a Path,
+    text: &'a str,
+    segments: &'a [Segment],
+}
+
+/// Decodes `path` into interleaved samples, with its sample rate and channel count.
+fn decode(path: &Path) -> Result<(Vec<f32>, u32, u16)> {
+    let file = File::open(path)?;
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            MediaSourceStream::new(Box::new(file), Default::default()),
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .context("unsupported audio format")?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .context("no audio track")?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .context("unknown sample rate")?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("unsupported codec")?;
+
+    let mut samples = Vec::new();
+    let mut channels = 1;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet only loses its own audio.
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        channels = decoded.spec().channels.count() as u16;
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+    }
+    Ok((samples, sample_rate, channels))
+}
+
+/// `seconds` as `HH:MM:SS` followed by `separator` and milliseconds.
+fn timestamp(seconds: f64, separator: char) -> String {
+    let ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+fn subtitles(segments: &[Segment], format: Format) -> String {
+    let mut out = String::new();
+    if format == Format::Vtt {
+        out.push_str("WEBVTT\n\n");
+    }
+    let separator = if format == Format::Srt { ',' } else { '.' };
+    let cues = segments
+        .iter()
+        .filter(|segment| !segment.text.trim().is_empty());
+    for (i, segment) in cues.enumerate() {
+        if format == Format::Srt {
+            out.push_str(&format!("{}\n", i + 1));
+        }
+        out.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            timestamp(segment.start, separator),
+            timestamp(segment.end, separator),
+            segment.text.trim()
+        ));
+    }
+    out
+}
+
+/// Renders the transcript of `file` in `format`.
+fn render(file: &Path, segments: &[Segment], format: Format) -> Result<String> {
+    let text: String = segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect();
+    let text = text.trim();
+    Ok(match format {
+        Format::Txt => format!("{}\n", text),
+        Format::Json => {
+            let transcript = JsonTranscript {
+                file,
+                text,
+                segments,
+            };
+            format!("{}\n", serde_json::to_string(&transcript)?)
+        }
+        Format::Srt | Format::Vtt => subtitles(segments, format),
+    })
+}
+
+/// `vyped transcribe`: transcribes audio files the way recordings are,
+/// with the model and language of a profile.
+pub fn transcribe_files(config: &AppConfig, args: &TranscribeArgs) -> Result<()> {
+    let name = args
+        .profile
+        .as_deref()
+        .unwrap_or(vype_shared::DEFAULT_PROFILE);
+    let Some(profile) = config.profile(name) else {
+        bail!("unknown profile `{}`", name);
+    };
+    let model_path = resolve_model_path(&profile)?;
+    info!("Loading model from {}", model_path);
+    let transcriber = WhisperTranscriber::new(&model_path)?;
+    let postprocessor = PostProcessor::new(&profile.postprocess)?;
+
+    let mut failed = 0;
+    for file in &args.files {
+        let result = decode(file)
+            .with_context(|| format!("failed to decode {}", file.display()))
+            .and_then(|(samples, sample_rate, channels)| {
+                let audio = resample_to_16khz_mono(&samples, sample_rate, channels);
+                info!(
+                    "Transcribing {} ({:.1}s)",
+                    file.display(),
+                    audio.len() as f64 / 16000.0
+                );
+                let options = TranscribeOptions {
+                    prompt: profile.initial_prompt(),
+                    words: args.words && args.format == Format::Json,
+                    ..Default::default()
+                };
+                let segments = transcriber.transcribe_with(&audio, &profile, options)?;
+                let level = peak_level(&audio, 16000);
+                let (segments, discarded) = filter_segments(segments, level, true, &config.filter);
+                for segment in discarded {
+                    info!(
+                        "Discarded likely hallucination at {:.1}s: {}",
+                        segment.start,
+                        segment.text.trim()
+                    );
+                }
+                Ok(segments
+                    .into_iter()
+                    .map(|segment| Segment {
+                        text: postprocessor.process(&segment.text),
+                        ..segment
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .and_then(|segments| render(file, &segments, args.format))
+            .and_then(|output| match &args.out_dir {
+                Some(dir) => {
+                    let stem = file.file_stem().unwrap_or(file.as_os_str());
+                    let path = dir.join(stem).with_extension(args.format.extension());
+                    std::fs::write(&path, output)
+                        .with_context(|| format!("failed to write {}", path.display()))
+                }
+                None => {
+                    print!("{}", output);
+                    Ok(())
+                }
+            });
+        if let Err(e) = result {
+            error!("{:#}", e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} file(s) failed", failed, args.files.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcriber::Word;
+
+    fn segments() -> Vec<Segment> {
+        vec![
+            Segment {
+                start: 0.0,
+                end: 2.5,
+                text: " Hello there.".to_string(),
+                no_speech_prob: 0.25,
+                avg_logprob: -0.5,
+                words: vec![Word {
+                    start: 0.0,
+                    end: 0.5,
+                    text: " Hello".to_string(),
+                    probability: 0.75,
+                }],
+            },
+            Segment {
+                start: 2.5,
+                end: 3661.0416,
+                text: " General Kenobi.".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_decode_wav() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tone.wav");
+        crate::recordings::write_wav(&path, &[0.0, 0.5, -0.5], 16000).unwrap();
+
+        let (samples, sample_rate, channels) = decode(&path).unwrap();
+        assert_eq!((sample_rate, channels), (16000, 1));
+        assert_eq!(samples.len(), 3);
+        assert!((samples[1] - 0.5).abs() < 1e-3 && (samples[2] + 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0.0, ','), "00:00:00,000");
+        assert_eq!(timestamp(3661.0416, '.'), "01:01:01.042");
+    }
+
+    #[test]
+    fn test_render() {
+        let file = Path::new("talk.wav");
+        assert_eq!(
+            render(file, &segments(), Format::Txt).unwrap(),
+            "Hello there. General Kenobi.\n"
+        );
+        assert_eq!(
+            render(file, &segments(), Format::Srt).unwrap(),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n\
+             2\n00:00:02,500 --> 01:01:01,042\nGeneral Kenobi.\n\n"
+        );
+        assert_eq!(
+            render(file, &segments(), Format::Vtt).unwrap(),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:02.500\nHello there.\n\n\
+             00:00:02.500 --> 01:01:01.042\nGeneral Kenobi.\n\n"
+        );
+        assert_eq!(
+            render(file, &segments()[..1], Format::Json).unwrap(),
+            "{\"file\":\"talk.wav\",\"text\":\"Hello there.\",\"segments\":\
+             [{\"start\":0.0,\"end\":2.5,\"text\":\" Hello there.\",\
+             \"no_speech_prob\":0.25,\"avg_logprob\":-0.5,\"words\":\
+             [{\"start\":0.0,\"end\":0.5,\"text\":\" Hello\",\"probability\":0.75}]}]}\n"
+        );
+    }
+}
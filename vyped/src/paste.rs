@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use log::warn;
+use std::time::{Duration, Instant};
+
+use crate::sink::{Shortcut, TextSink};
+
+/// Inserts text by putting it on the clipboard and pressing the paste shortcut.
+#[derive(Default)]
+pub struct Paster {
+    /// Opened on first use so typing-only setups never touch the clipboard.
+    clipboard: Option<arboard::Clipboard>,
+    /// Clipboard text from before pasting, and when to put it back.
+    restore: Option<(Instant, Option<String>)>,
+}
+
+impl Paster {
+    /// Pastes `text` with `shortcut`. The previous clipboard text is put back
+    /// by [`Self::restore_due`] once the application had `restore_delay`
+    /// seconds to read it.
+    pub fn paste(
+        &mut self,
+        sink: &mut dyn TextSink,
+        text: &str,
+        shortcut: &Shortcut,
+        restore_delay: f64,
+    ) -> Result<()> {
+        let clipboard = match &mut self.clipboard {
+            Some(clipboard) => clipboard,
+            None => self
+                .clipboard
+                .insert(arboard::Clipboard::new().context("failed to open the clipboard")?),
+        };
+
+        // Only text is restored; images and other formats are lost. A paste
+        // before the last one was restored keeps the text from before both.
+        let previous = match self.restore.take() {
+            Some((_, previous)) => previous,
+            None => clipboard.get_text().ok(),
+        };
+        let deadline = Instant::now() + Duration::from_secs_f64(restore_delay);
+        self.restore = Some((deadline, previous));
+        clipboard
+            .set_text(text)
+            .context("failed to set the clipboard")?;
+        sink.shortcut(shortcut)
+    }
+
+    /// Puts back the clipboard text from before the last paste once its
+    /// restore delay has passed.
+    pub fn restore_due(&mut self) {
+        if self
+            .restore
+            .as_ref()
+            .is_some_and(|(deadline, _)| Instant::now() >= *deadline)
+        {
+            self.restore_now();
+        }
+    }
+
+    fn restore_now(&mut self) {
+        let Some((_, previous)) = self.restore.take() else {
+            return;
+        };
+        if let Some(previous) = previous
+            && let Some(clipboard) = &mut self.clipboard
+            && let Err(e) = clipboard.set_text(previous)
+        {
+            warn!("Failed to restore the clipboard: {}", e);
+        }
+    }
+}
+
+impl Drop for Paster {
+    fn drop(&mut self) {
+        self.restore_now();
+    }
+}
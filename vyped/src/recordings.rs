@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use log::{debug, error};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use vype_shared::RecordingsConfig;
+
+use crate::resample::resample_to_16khz_mono;
+
+const WAV_SAMPLE_RATE: u32 = 16000;
+
+/// Written next to a saved recording: what it was transcribed with and into.
+#[derive(Debug, Serialize)]
+pub struct Sidecar {
+    /// Unix time in milliseconds, also the file name of the recording.
+    pub timestamp: u64,
+    /// Seconds of audio recorded.
+    pub duration: f64,
+    pub profile: String,
+    pub model: String,
+    pub language: String,
+    pub translate: bool,
+    pub trim_silence: bool,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn recordings_dir(config: &RecordingsConfig) -> Option<PathBuf> {
+    match &config.dir {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => dirs::data_dir().map(|dir| dir.join("vype").join("recordings")),
+    }
+}
+
+/// Saves captured mono `samples` as `<timestamp>.wav` and the sidecar as
+/// `<timestamp>.json` on a thread of its own, then deletes the oldest
+/// recordings beyond the configured limits.
+pub fn save_recording(
+    config: &RecordingsConfig,
+    samples: Vec<f32>,
+    sample_rate: u32,
+    sidecar: Sidecar,
+) {
+    let config = config.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = save(&config, &samples, sample_rate, &sidecar) {
+            error!("Failed to save recording: {:#}", e);
+        }
+    });
+}
+
+fn save(
+    config: &RecordingsConfig,
+    samples: &[f32],
+    sample_rate: u32,
+    sidecar: &Sidecar,
+) -> Result<()> {
+    let dir = recordings_dir(config).context("could not determine the data directory")?;
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let audio = resample_to_16khz_mono(samples, sample_rate, 1);
+    let path = dir.join(format!("{}.wav", sidecar.timestamp));
+    write_wav(&path, &audio, WAV_SAMPLE_RATE)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    fs::write(
+        path.with_extension("json"),
+        serde_json::to_string_pretty(sidecar)?,
+    )?;
+    debug!("Saved recording to {}", path.display());
+
+    prune(&dir, config.max_count, config.max_size_mb * 1024 * 1024)
+}
+
+/// Writes mono `samples` as 16-bit PCM.
+pub fn write_wav(path: &Path, samples: &[f32], sample_rate: u32) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_len).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel.
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * 2).to_le_bytes())?;
+    // Bytes per frame and bits per sample.
+    file.write_all(&2u16.to_le_bytes())?;
+    file.write_all(&16u16.to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        file.write_all(&sample.to_le_bytes())?;
+    }
+    file.flush()
+}
+
+/// Deletes the oldest recordings in `dir`, with their sidecars, so that at
+/// most `max_count` remain and they take up at most `max_bytes`.
+fn prune(dir: &Path, max_count: usize, max_bytes: u64) -> Result<()> {
+    let mut recordings = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "wav") {
+            let size = [path.clone(), path.with_extension("json")]
+                .iter()
+                .filter_map(|path| fs::metadata(path).ok())
+                .map(|metadata| metadata.len())
+                .sum::<u64>();
+            recordings.push((path, size));
+        }
+    }
+    // File names are timestamps of the same length, so they sort by age.
+    recordings.sort();
+
+    let sizes: Vec<u64> = recordings.iter().map(|(_, size)| *size).collect();
+    for (path, _) in &recordings[..expired(&sizes, max_count, max_bytes)] {
+        debug!("Deleting old recording {}", path.display());
+        fs::remove_file(path)?;
+        match fs::remove_file(path.with_extension("json")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// How many of the oldest recordings, with `sizes` from oldest to newest,
+/// go over the limits. A limit of 0 is no limit, and the newest recording is
+/// always kept.
+fn expired(sizes: &[u64], max_count: usize, max_bytes: u64) -> usize {
+    let mut total: u64 = sizes.iter().sum();
+    let mut expired = 0;
+    while expired + 1 < sizes.len()
+        && ((max_count > 0 && sizes.len() - expired > max_count)
+            || (max_bytes > 0 && total > max_bytes))
+    {
+        total -= sizes[expired];
+        expired += 1;
+    }
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired() {
+        assert_eq!(expired(&[], 2, 10), 0);
+        assert_eq!(expired(&[1, 1, 1, 1], 2, 0), 2);
+        assert_eq!(expired(&[5, 3, 4, 2], 0, 7), 2);
+        assert_eq!(expired(&[5, 3, 4, 2], 3, 10), 1);
+        assert_eq!(expired(&[5, 3, 4, 2], 0, 0), 0);
+        // The newest recording stays even when it alone is too large.
+        assert_eq!(expired(&[1, 20], 0, 10), 1);
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.wav");
+        write_wav(&path, &[0.0, 1.0, -1.0, 2.0], 16000).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 16000);
+        assert_eq!(&bytes[36..40], b"data");
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        assert_eq!(samples, vec![0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        for timestamp in [1700000000000u64, 1700000000001, 1700000000002] {
+            let path = dir.path().join(format!("{}.wav", timestamp));
+            write_wav(&path, &[0.0; 10], 16000).unwrap();
+            fs::write(path.with_extension("json"), "{}").unwrap();
+        }
+
+        prune(dir.path(), 2, 0).unwrap();
+        let mut names: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "1700000000001.json",
+                "1700000000001.wav",
+                "1700000000002.json",
+                "1700000000002.wav"
+            ]
+        );
+    }
+}
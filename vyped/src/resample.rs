@@ -6,7 +6,7 @@ pub fn resample_to_16khz_mono(samples: &[f32], from_rate: u32, channels: u16) ->
     }
 
     let mono = if channels > 1 {
-        stereo_to_mono(samples, channels as usize)
+        downmix(samples, channels as usize).collect()
     } else {
         samples.to_vec()
     };
@@ -18,17 +18,11 @@ pub fn resample_to_16khz_mono(samples: &[f32], from_rate: u32, channels: u16) ->
     resample(&mono, from_rate, 16000)
 }
 
-fn stereo_to_mono(samples: &[f32], channels: usize) -> Vec<f32> {
-    let frames = samples.len() / channels;
-    let mut mono = Vec::with_capacity(frames);
-
-    for frame_idx in 0..frames {
-        let offset = frame_idx * channels;
-        let sum: f32 = (0..channels).map(|c| samples[offset + c]).sum();
-        mono.push(sum / channels as f32);
-    }
-
-    mono
+/// Averages each frame of interleaved `samples` into one mono sample.
+pub fn downmix(samples: &[f32], channels: usize) -> impl Iterator<Item = f32> + '_ {
+    samples
+        .chunks_exact(channels)
+        .map(move |frame| frame.iter().sum::<f32>() / channels as f32)
 }
 
 fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
@@ -0,0 +1,146 @@
+mod uinput;
+mod wayland;
+mod xdo;
+
+use anyhow::{Result, bail};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use vype_shared::{KeyOp, Output};
+
+use crate::hotkey::Modifier;
+
+/// A key combination such as `ctrl+v` or `shift+Insert`, sent to paste.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortcut {
+    pub modifiers: Vec<Modifier>,
+    /// XKB keysym name: a lowercase ASCII character or `Insert`.
+    pub key: String,
+}
+
+impl FromStr for Shortcut {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let parts: Vec<String> = spec.split('+').map(|p| p.trim().to_lowercase()).collect();
+        let (key, modifiers) = parts.split_last().expect("split yields at least one part");
+
+        let key = match key.as_str() {
+            "insert" => "Insert".to_string(),
+            key if key.len() == 1 && key.is_ascii() && key != " " => key.to_string(),
+            _ => bail!("unsupported key `{}` in shortcut `{}`", key, spec),
+        };
+        let modifiers = modifiers
+            .iter()
+            .map(|name| {
+                Modifier::parse(name).ok_or_else(|| {
+                    anyhow::anyhow!("`{}` in shortcut `{}` is not a modifier", name, spec)
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { modifiers, key })
+    }
+}
+
+/// Types [`KeyOp`]s into whatever window has keyboard focus.
+pub trait TextSink {
+    fn name(&self) -> &'static str;
+
+    /// Performs `op`, waiting `key_delay` between keystrokes.
+    fn execute(&mut self, op: &KeyOp, key_delay: Duration) -> Result<()>;
+
+    fn shortcut(&mut self, shortcut: &Shortcut) -> Result<()>;
+
+    fn execute_all(&mut self, ops: &[KeyOp], key_delay: Duration) -> Result<()> {
+        for op in ops {
+            self.execute(op, key_delay)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn env_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+fn open(output: Output) -> Result<Box<dyn TextSink>> {
+    let sink: Box<dyn TextSink> = match output {
+        Output::Xdo => Box::new(xdo::XdoSink::new()?),
+        Output::Uinput => Box::new(uinput::UinputSink::new()?),
+        Output::Wayland => Box::new(wayland::WaylandSink::new()?),
+        Output::Auto if env_set("WAYLAND_DISPLAY") => match wayland::WaylandSink::new() {
+            Ok(sink) => Box::new(sink),
+            // GNOME, for one, does not implement the virtual keyboard protocol.
+            Err(e) => {
+                warn!(
+                    "Wayland typing unavailable ({:#}), falling back to uinput",
+                    e
+                );
+                Box::new(uinput::UinputSink::new()?)
+            }
+        },
+        Output::Auto if env_set("DISPLAY") => Box::new(xdo::XdoSink::new()?),
+        Output::Auto => Box::new(uinput::UinputSink::new()?),
+    };
+    info!("Typing with {} (output {})", sink.name(), output.as_str());
+    Ok(sink)
+}
+
+/// Typing backends used by the configured profiles, each opened once.
+pub struct Sinks {
+    sinks: HashMap<Output, Box<dyn TextSink>>,
+}
+
+impl Sinks {
+    /// Opens the backends in `outputs` that are not in `opened` already.
+    pub fn open(outputs: impl IntoIterator<Item = Output>, opened: Option<&Sinks>) -> Result<Self> {
+        let mut sinks = HashMap::new();
+        for output in outputs {
+            let reusable = opened.is_some_and(|opened| opened.sinks.contains_key(&output));
+            if !reusable && !sinks.contains_key(&output) {
+                sinks.insert(output, open(output)?);
+            }
+        }
+        Ok(Self { sinks })
+    }
+
+    /// Takes over the backends from `previous` that are still in use.
+    pub fn reuse(&mut self, outputs: impl IntoIterator<Item = Output>, mut previous: Sinks) {
+        for output in outputs {
+            if let Some(sink) = previous.sinks.remove(&output) {
+                self.sinks.insert(output, sink);
+            }
+        }
+    }
+
+    pub fn get(&mut self, output: Output) -> &mut dyn TextSink {
+        self.sinks
+            .get_mut(&output)
+            .expect("sinks are opened for every profile")
+            .as_mut()
+    }
+
+    pub fn name(&self, output: Output) -> &'static str {
+        self.sinks[&output].name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shortcut() {
+        let shortcut: Shortcut = "Ctrl+Shift+V".parse().unwrap();
+        assert_eq!(shortcut.modifiers, vec![Modifier::Ctrl, Modifier::Shift]);
+        assert_eq!(shortcut.key, "v");
+
+        let shortcut: Shortcut = "shift+insert".parse().unwrap();
+        assert_eq!(shortcut.key, "Insert");
+
+        assert!("ctrl+F5".parse::<Shortcut>().is_err());
+        assert!("v+ctrl".parse::<Shortcut>().is_err());
+        assert!("ctrl+".parse::<Shortcut>().is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result, bail};
+use evdev::uinput::VirtualDevice;
+use evdev::{AttributeSet, InputEvent, KeyCode, KeyEvent};
+use std::time::Duration;
+use vype_shared::KeyOp;
+
+use super::{Shortcut, TextSink};
+use crate::hotkey::Modifier;
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::KEY_A,
+    KeyCode::KEY_B,
+    KeyCode::KEY_C,
+    KeyCode::KEY_D,
+    KeyCode::KEY_E,
+    KeyCode::KEY_F,
+    KeyCode::KEY_G,
+    KeyCode::KEY_H,
+    KeyCode::KEY_I,
+    KeyCode::KEY_J,
+    KeyCode::KEY_K,
+    KeyCode::KEY_L,
+    KeyCode::KEY_M,
+    KeyCode::KEY_N,
+    KeyCode::KEY_O,
+    KeyCode::KEY_P,
+    KeyCode::KEY_Q,
+    KeyCode::KEY_R,
+    KeyCode::KEY_S,
+    KeyCode::KEY_T,
+    KeyCode::KEY_U,
+    KeyCode::KEY_V,
+    KeyCode::KEY_W,
+    KeyCode::KEY_X,
+    KeyCode::KEY_Y,
+    KeyCode::KEY_Z,
+];
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::KEY_0,
+    KeyCode::KEY_1,
+    KeyCode::KEY_2,
+    KeyCode::KEY_3,
+    KeyCode::KEY_4,
+    KeyCode::KEY_5,
+    KeyCode::KEY_6,
+    KeyCode::KEY_7,
+    KeyCode::KEY_8,
+    KeyCode::KEY_9,
+];
+
+/// Characters produced by a key together with Shift on a US layout.
+const SHIFTED_DIGITS: &str = ")!@#$%^&*(";
+
+const SYMBOLS: &[(char, char, KeyCode)] = &[
+    ('-', '_', KeyCode::KEY_MINUS),
+    ('=', '+', KeyCode::KEY_EQUAL),
+    ('[', '{', KeyCode::KEY_LEFTBRACE),
+    (']', '}', KeyCode::KEY_RIGHTBRACE),
+    ('\\', '|', KeyCode::KEY_BACKSLASH),
+    (';', ':', KeyCode::KEY_SEMICOLON),
+    ('\'', '"', KeyCode::KEY_APOSTROPHE),
+    ('`', '~', KeyCode::KEY_GRAVE),
+    (',', '<', KeyCode::KEY_COMMA),
+    ('.', '>', KeyCode::KEY_DOT),
+    ('/', '?', KeyCode::KEY_SLASH),
+];
+
+/// Key and Shift state that type `c` with a US keyboard layout.
+fn us_key(c: char) -> Option<(KeyCode, bool)> {
+    match c {
+        'a'..='z' => Some((LETTERS[c as usize - 'a' as usize], false)),
+        'A'..='Z' => Some((LETTERS[c as usize - 'A' as usize], true)),
+        '0'..='9' => Some((DIGITS[c as usize - '0' as usize], false)),
+        ' ' => Some((KeyCode::KEY_SPACE, false)),
+        '\n' => Some((KeyCode::KEY_ENTER, false)),
+        '\t' => Some((KeyCode::KEY_TAB, false)),
+        _ => {
+            if let Some(digit) = SHIFTED_DIGITS.find(c) {
+                return Some((DIGITS[digit], true));
+            }
+            SYMBOLS.iter().find_map(|&(plain, shifted, key)| {
+                (c == plain || c == shifted).then_some((key, c == shifted))
+            })
+        }
+    }
+}
+
+/// Types through a virtual keyboard created with `/dev/uinput`. Works on any
+/// display server, but assumes a US layout and cannot type other characters.
+pub struct UinputSink {
+    device: VirtualDevice,
+}
+
+impl UinputSink {
+    pub fn new() -> Result<Self> {
+        let mut keys = AttributeSet::<KeyCode>::new();
+        for key in LETTERS.iter().chain(&DIGITS) {
+            keys.insert(*key);
+        }
+        for &(_, _, key) in SYMBOLS {
+            keys.insert(key);
+        }
+        for key in [
+            KeyCode::KEY_SPACE,
+            KeyCode::KEY_ENTER,
+            KeyCode::KEY_TAB,
+            KeyCode::KEY_LEFTSHIFT,
+            KeyCode::KEY_LEFTCTRL,
+            KeyCode::KEY_LEFTALT,
+            KeyCode::KEY_LEFTMETA,
+            KeyCode::KEY_INSERT,
+            KeyCode::KEY_BACKSPACE,
+            KeyCode::KEY_DELETE,
+            KeyCode::KEY_LEFT,
+            KeyCode::KEY_RIGHT,
+        ] {
+            keys.insert(key);
+        }
+
+        let device = VirtualDevice::builder()
+            .and_then(|builder| builder.name("vype virtual keyboard").with_keys(&keys))
+            .and_then(|builder| builder.build())
+            .context("failed to create uinput device (is /dev/uinput writable?)")?;
+        Ok(Self { device })
+    }
+
+    fn emit(&mut self, events: &[InputEvent]) -> Result<()> {
+        self.device.emit(events)?;
+        Ok(())
+    }
+
+    fn tap(&mut self, key: KeyCode, shift: bool) -> Result<()> {
+        if shift {
+            self.chord(&[KeyCode::KEY_LEFTSHIFT, key])
+        } else {
+            self.chord(&[key])
+        }
+    }
+
+    /// Presses `keys` in order, then releases them in reverse.
+    fn chord(&mut self, keys: &[KeyCode]) -> Result<()> {
+        let down: Vec<InputEvent> = keys.iter().map(|key| *KeyEvent::new(*key, 1)).collect();
+        let up: Vec<InputEvent> = keys
+            .iter()
+            .rev()
+            .map(|key| *KeyEvent::new(*key, 0))
+            .collect();
+        self.emit(&down)?;
+        self.emit(&up)
+    }
+
+    fn repeat(&mut self, key: KeyCode, n: usize, key_delay: Duration) -> Result<()> {
+        for _ in 0..n {
+            self.tap(key, false)?;
+            std::thread::sleep(key_delay);
+        }
+        Ok(())
+    }
+}
+
+impl TextSink for UinputSink {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    fn execute(&mut self, op: &KeyOp, key_delay: Duration) -> Result<()> {
+        match op {
+            KeyOp::Backspace(n) => self.repeat(KeyCode::KEY_BACKSPACE, *n, key_delay),
+            KeyOp::Delete(n) => self.repeat(KeyCode::KEY_DELETE, *n, key_delay),
+            KeyOp::Left(n) => self.repeat(KeyCode::KEY_LEFT, *n, key_delay),
+            KeyOp::Right(n) => self.repeat(KeyCode::KEY_RIGHT, *n, key_delay),
+            KeyOp::Type(text) => {
+                // Refuse the whole run rather than typing part of it.
+                let keys = text
+                    .chars()
+                    .map(|c| {
+                        us_key(c).ok_or_else(|| {
+                            anyhow::anyhow!("uinput output cannot type `{}`; use xdo or wayland", c)
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                for (key, shift) in keys {
+                    self.tap(key, shift)?;
+                    std::thread::sleep(key_delay);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn shortcut(&mut self, shortcut: &Shortcut) -> Result<()> {
+        let mut keys: Vec<KeyCode> = shortcut
+            .modifiers
+            .iter()
+            .map(|modifier| match modifier {
+                Modifier::Ctrl => KeyCode::KEY_LEFTCTRL,
+                Modifier::Alt => KeyCode::KEY_LEFTALT,
+                Modifier::Shift => KeyCode::KEY_LEFTSHIFT,
+                Modifier::Meta => KeyCode::KEY_LEFTMETA,
+            })
+            .collect();
+        let key = match shortcut.key.as_str() {
+            "Insert" => KeyCode::KEY_INSERT,
+            key => match key.chars().next().and_then(us_key) {
+                Some((key, false)) => key,
+                _ => bail!("uinput output cannot send `{}`", shortcut.key),
+            },
+        };
+        keys.push(key);
+        self.chord(&keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_us_key() {
+        assert_eq!(us_key('a'), Some((KeyCode::KEY_A, false)));
+        assert_eq!(us_key('Z'), Some((KeyCode::KEY_Z, true)));
+        assert_eq!(us_key('7'), Some((KeyCode::KEY_7, false)));
+        assert_eq!(us_key('!'), Some((KeyCode::KEY_1, true)));
+        assert_eq!(us_key('?'), Some((KeyCode::KEY_SLASH, true)));
+        assert_eq!(us_key('.'), Some((KeyCode::KEY_DOT, false)));
+        assert_eq!(us_key('é'), None);
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::{Seek, Write};
+use std::os::fd::AsFd;
+use std::time::{Duration, Instant};
+use vype_shared::KeyOp;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::wl_keyboard::{KeyState, KeymapFormat};
+use wayland_client::protocol::wl_registry::WlRegistry;
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle, delegate_noop};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1;
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1;
+
+use super::{Shortcut, TextSink};
+use crate::hotkey::Modifier;
+
+/// Keycodes above 255 cannot be used by X clients behind XWayland.
+const MAX_KEYSYMS: usize = 247;
+
+struct State;
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore WlSeat);
+delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(State: ZwpVirtualKeyboardV1);
+
+fn keysym(c: char) -> String {
+    match c {
+        '\n' => "Return".to_string(),
+        '\t' => "Tab".to_string(),
+        c => format!("U{:04X}", c as u32),
+    }
+}
+
+/// Splits `keysyms` into runs that each fit into one keymap.
+fn keymap_chunks(keysyms: &[String]) -> Vec<&[String]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut distinct = HashSet::new();
+    for (i, keysym) in keysyms.iter().enumerate() {
+        if !distinct.contains(keysym) && distinct.len() == MAX_KEYSYMS {
+            chunks.push(&keysyms[start..i]);
+            start = i;
+            distinct.clear();
+        }
+        distinct.insert(keysym);
+    }
+    if start < keysyms.len() {
+        chunks.push(&keysyms[start..]);
+    }
+    chunks
+}
+
+/// An XKB keymap where evdev keycode `i + 1` produces `keysyms[i]`.
+fn keymap(keysyms: &[String]) -> String {
+    let mut keycodes = String::new();
+    let mut symbols = String::new();
+    for (i, keysym) in keysyms.iter().enumerate() {
+        keycodes.push_str(&format!("    <K{}> = {};\n", i + 1, i + 9));
+        symbols.push_str(&format!("    key <K{}> {{ [ {} ] }};\n", i + 1, keysym));
+    }
+    format!(
+        "xkb_keymap {{\n\
+         xkb_keycodes \"vype\" {{\n    minimum = 8;\n    maximum = 255;\n{}}};\n\
+         xkb_types \"vype\" {{ include \"complete\" }};\n\
+         xkb_compatibility \"vype\" {{ include \"complete\" }};\n\
+         xkb_symbols \"vype\" {{\n{}}};\n\
+         }};\n",
+        keycodes, symbols
+    )
+}
+
+/// Types through the `zwp_virtual_keyboard_v1` protocol, supported by wlroots
+/// based compositors and KDE. Every character gets its own key in a keymap
+/// uploaded on demand, so any Unicode text can be typed.
+pub struct WaylandSink {
+    connection: Connection,
+    queue: EventQueue<State>,
+    keyboard: ZwpVirtualKeyboardV1,
+    keysyms: Vec<String>,
+    started_at: Instant,
+}
+
+impl WaylandSink {
+    pub fn new() -> Result<Self> {
+        let connection =
+            Connection::connect_to_env().context("failed to connect to the Wayland compositor")?;
+        let (globals, queue) = registry_queue_init::<State>(&connection)?;
+        let qh = queue.handle();
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ()).context("no Wayland seat")?;
+        let manager: ZwpVirtualKeyboardManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .context("compositor does not support the virtual keyboard protocol")?;
+        let keyboard = manager.create_virtual_keyboard(&seat, &qh, ());
+
+        let mut sink = Self {
+            connection,
+            queue,
+            keyboard,
+            keysyms: Vec::new(),
+            started_at: Instant::now(),
+        };
+        // Compositors reject key events until a keymap has been set.
+        sink.upload_keymap()?;
+        Ok(sink)
+    }
+
+    fn upload_keymap(&mut self) -> Result<()> {
+        let mut file = tempfile::tempfile()?;
+        let keymap = keymap(&self.keysyms);
+        file.write_all(keymap.as_bytes())?;
+        file.write_all(b"\0")?;
+        file.flush()?;
+        file.rewind()?;
+        self.keyboard.keymap(
+            KeymapFormat::XkbV1 as u32,
+            file.as_fd(),
+            keymap.len() as u32 + 1,
+        );
+        self.queue.roundtrip(&mut State)?;
+        Ok(())
+    }
+
+    /// Evdev keycodes producing `keysyms`, which must fit into one keymap.
+    /// Missing keysyms are added with a single keymap upload.
+    fn keycodes(&mut self, keysyms: &[String]) -> Result<Vec<u32>> {
+        let missing = |known: &[String]| {
+            let mut missing: Vec<String> = Vec::new();
+            for keysym in keysyms {
+                if !known.contains(keysym) && !missing.contains(keysym) {
+                    missing.push(keysym.clone());
+                }
+            }
+            missing
+        };
+        let mut added = missing(&self.keysyms);
+        if !added.is_empty() {
+            if self.keysyms.len() + added.len() > MAX_KEYSYMS {
+                self.keysyms.clear();
+                added = missing(&self.keysyms);
+            }
+            self.keysyms.extend(added);
+            self.upload_keymap()?;
+        }
+        Ok(keysyms
+            .iter()
+            .map(|keysym| self.keysyms.iter().position(|k| k == keysym).unwrap() as u32 + 1)
+            .collect())
+    }
+
+    fn keycode(&mut self, keysym: &str) -> Result<u32> {
+        Ok(self.keycodes(&[keysym.to_string()])?[0])
+    }
+
+    fn tap(&mut self, keycode: u32, n: usize, key_delay: Duration) -> Result<()> {
+        for _ in 0..n {
+            let time = self.started_at.elapsed().as_millis() as u32;
+            self.keyboard.key(time, keycode, KeyState::Pressed as u32);
+            self.keyboard.key(time, keycode, KeyState::Released as u32);
+            if !key_delay.is_zero() {
+                self.connection.flush()?;
+                std::thread::sleep(key_delay);
+            }
+        }
+        self.connection.flush()?;
+        Ok(())
+    }
+}
+
+impl TextSink for WaylandSink {
+    fn name(&self) -> &'static str {
+        "wayland"
+    }
+
+    fn execute(&mut self, op: &KeyOp, key_delay: Duration) -> Result<()> {
+        let (keysym, n) = match op {
+            KeyOp::Backspace(n) => ("BackSpace", *n),
+            KeyOp::Delete(n) => ("Delete", *n),
+            KeyOp::Left(n) => ("Left", *n),
+            KeyOp::Right(n) => ("Right", *n),
+            KeyOp::Type(text) => {
+                let keysyms: Vec<String> = text.chars().map(keysym).collect();
+                for chunk in keymap_chunks(&keysyms) {
+                    for keycode in self.keycodes(chunk)? {
+                        self.tap(keycode, 1, key_delay)?;
+                    }
+                }
+                return Ok(());
+            }
+        };
+        let keycode = self.keycode(keysym)?;
+        self.tap(keycode, n, key_delay)
+    }
+
+    fn shortcut(&mut self, shortcut: &Shortcut) -> Result<()> {
+        let keycode = self.keycode(&shortcut.key)?;
+        // Bits of the standard XKB modifiers in the "complete" keymap.
+        let mask = shortcut.modifiers.iter().fold(0, |mask, modifier| {
+            mask | match modifier {
+                Modifier::Shift => 1,
+                Modifier::Ctrl => 4,
+                Modifier::Alt => 8,
+                Modifier::Meta => 64,
+            }
+        });
+        self.keyboard.modifiers(mask, 0, 0, 0);
+        self.tap(keycode, 1, Duration::ZERO)?;
+        self.keyboard.modifiers(0, 0, 0, 0);
+        self.connection.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keymap() {
+        assert_eq!(keysym('é'), "U00E9");
+        assert_eq!(keysym('\n'), "Return");
+
+        let keymap = keymap(&["BackSpace".to_string(), "U0061".to_string()]);
+        assert!(keymap.contains("<K1> = 9;"));
+        assert!(keymap.contains("key <K2> { [ U0061 ] };"));
+    }
+
+    #[test]
+    fn test_keymap_chunks() {
+        let keysyms: Vec<String> = (0..MAX_KEYSYMS + 10)
+            .map(|i| format!("U{:04X}", 0x100 + i))
+            .collect();
+        let mut text = keysyms.clone();
+        text.insert(5, keysyms[0].clone());
+
+        let chunks = keymap_chunks(&text);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), MAX_KEYSYMS + 1);
+        assert_eq!(chunks[1], &keysyms[MAX_KEYSYMS..]);
+        assert!(keymap_chunks(&[]).is_empty());
+    }
+}
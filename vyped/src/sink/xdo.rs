@@ -0,0 +1,60 @@
+use anyhow::Result;
+use libxdo::XDo;
+use std::time::Duration;
+use vype_shared::KeyOp;
+
+use super::{Shortcut, TextSink};
+use crate::hotkey::Modifier;
+
+/// Types through libxdo, which only reaches X11 and XWayland windows.
+pub struct XdoSink {
+    xdo: XDo,
+}
+
+impl XdoSink {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            xdo: XDo::new(None)?,
+        })
+    }
+
+    fn repeat(&self, keysym: &str, n: usize, delay_us: u32) -> Result<()> {
+        for _ in 0..n {
+            self.xdo.send_keysequence(keysym, delay_us)?;
+        }
+        Ok(())
+    }
+}
+
+impl TextSink for XdoSink {
+    fn name(&self) -> &'static str {
+        "xdo"
+    }
+
+    fn execute(&mut self, op: &KeyOp, key_delay: Duration) -> Result<()> {
+        let delay_us = key_delay.as_micros().try_into().unwrap_or(u32::MAX);
+        match op {
+            KeyOp::Backspace(n) => self.repeat("BackSpace", *n, delay_us)?,
+            KeyOp::Delete(n) => self.repeat("Delete", *n, delay_us)?,
+            KeyOp::Type(text) => self.xdo.enter_text(text, delay_us)?,
+            KeyOp::Left(n) => self.repeat("Left", *n, delay_us)?,
+            KeyOp::Right(n) => self.repeat("Right", *n, delay_us)?,
+        }
+        Ok(())
+    }
+
+    fn shortcut(&mut self, shortcut: &Shortcut) -> Result<()> {
+        let mut sequence = String::new();
+        for modifier in &shortcut.modifiers {
+            sequence.push_str(match modifier {
+                Modifier::Ctrl => "ctrl+",
+                Modifier::Alt => "alt+",
+                Modifier::Shift => "shift+",
+                Modifier::Meta => "super+",
+            });
+        }
+        sequence.push_str(&shortcut.key);
+        self.xdo.send_keysequence(&sequence, 0)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use crate::transcriber::Segment;
+
+/// Audio a pass may cover before all but its last segment are finalized.
+const WINDOW_SECS: f64 = 15.0;
+/// Longest window kept open at all; Whisper only hears 30 seconds at a time.
+const MAX_WINDOW_SECS: f64 = 25.0;
+/// Finalized audio that is transcribed again, so a word cut at the boundary
+/// is heard in full. Words it repeats are dropped when merging.
+const OVERLAP_SECS: f64 = 1.0;
+/// Characters of finalized text given to Whisper as the prompt.
+const PROMPT_CHARS: usize = 200;
+/// Most words at the start of a pass that may repeat the finalized text.
+const MAX_REPEATED_WORDS: usize = 8;
+
+fn samples(secs: f64, sample_rate: u32) -> usize {
+    (secs * sample_rate as f64) as usize
+}
+
+/// Lowercase letters and digits of `word`, so repeats match despite punctuation.
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// `text` without its first `n` words.
+fn skip_words(text: &str, n: usize) -> &str {
+    let mut rest = text;
+    for _ in 0..n {
+        rest = rest.trim_start();
+        rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+    }
+    rest
+}
+
+/// Appends `text` to `finalized`, dropping the words at its start that
+/// repeat the end of `finalized`.
+fn merge(finalized: &mut String, text: &str) {
+    let old: Vec<String> = finalized.split_whitespace().map(normalize).collect();
+    let new: Vec<String> = text.split_whitespace().map(normalize).collect();
+    let max = MAX_REPEATED_WORDS.min(old.len()).min(new.len());
+    let repeated = (1..=max)
+        .rev()
+        .find(|&n| old[old.len() - n..] == new[..n])
+        .unwrap_or(0);
+    finalized.push_str(skip_words(text, repeated));
+}
+
+/// Transcript of a recording built up pass by pass. Once a pass covers more
+/// than `WINDOW_SECS` of audio its older segments are finalized, so the next
+/// pass only transcribes the audio after them and partial transcriptions
+/// take about as long late in a recording as early on.
+#[derive(Default)]
+pub struct StreamingTranscript {
+    finalized: String,
+    /// Samples of the recording that `finalized` covers.
+    finalized_until: usize,
+}
+
+impl StreamingTranscript {
+    pub fn reset(&mut self) {
+        self.finalized.clear();
+        self.finalized_until = 0;
+    }
+
+    /// First sample of the recording the next pass transcribes.
+    pub fn window_start(&self, sample_rate: u32) -> usize {
+        self.finalized_until
+            .saturating_sub(samples(OVERLAP_SECS, sample_rate))
+    }
+
+    /// The end of the finalized text, for [`crate::transcriber::TranscribeOptions::prompt`].
+    pub fn prompt(&self) -> &str {
+        let text = self.finalized.trim();
+        let mut start = text.len().saturating_sub(PROMPT_CHARS);
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        text[start..].trim_start()
+    }
+
+    /// Merges a pass over `window_len` samples from `window_start` on and
+    /// returns the transcript so far.
+    pub fn update(
+        &mut self,
+        segments: &[Segment],
+        window_start: usize,
+        window_len: usize,
+        sample_rate: u32,
+    ) -> String {
+        let window_secs = window_len as f64 / sample_rate as f64;
+        let overlap = self.finalized_until.saturating_sub(window_start) as f64 / sample_rate as f64;
+        // Segments ending inside the overlap were finalized by an earlier pass.
+        let segments: Vec<&Segment> = segments.iter().filter(|s| s.end > overlap).collect();
+
+        let finalize = if window_secs > MAX_WINDOW_SECS {
+            segments.len()
+        } else if window_secs > WINDOW_SECS {
+            segments.len().saturating_sub(1)
+        } else {
+            0
+        };
+        for segment in &segments[..finalize] {
+            merge(&mut self.finalized, &segment.text);
+            self.finalized_until = window_start + samples(segment.end, sample_rate);
+        }
+
+        let mut transcript = self.finalized.clone();
+        for segment in &segments[finalize..] {
+            merge(&mut transcript, &segment.text);
+        }
+        transcript.trim().to_string()
+    }
+
+    /// Merges the last pass, which covers the rest of the recording, and
+    /// returns the whole transcript.
+    pub fn finish(&mut self, segments: &[Segment], sample_rate: u32) -> String {
+        let window_start = self.window_start(sample_rate);
+        let overlap = self.finalized_until.saturating_sub(window_start) as f64 / sample_rate as f64;
+        for segment in segments.iter().filter(|s| s.end > overlap) {
+            merge(&mut self.finalized, &segment.text);
+        }
+        self.finalized.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 100;
+
+    fn segment(start: f64, end: f64, text: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut text = " Hello there.".to_string();
+        merge(&mut text, " there, how are you");
+        assert_eq!(text, " Hello there. how are you");
+
+        merge(&mut text, " Fine");
+        assert_eq!(text, " Hello there. how are you Fine");
+        assert_eq!(skip_words(" a b  c", 2), "  c");
+    }
+
+    #[test]
+    fn test_streaming_transcript() {
+        let mut stream = StreamingTranscript::default();
+        assert_eq!(stream.window_start(RATE), 0);
+
+        // A short window stays open.
+        let text = stream.update(&[segment(0.0, 4.0, " One two.")], 0, 500, RATE);
+        assert_eq!(text, "One two.");
+        assert_eq!(stream.window_start(RATE), 0);
+
+        // Past the window, all but the last segment are finalized.
+        let segments = [
+            segment(0.0, 4.0, " One two."),
+            segment(4.0, 12.0, " Three four."),
+            segment(12.0, 16.0, " Five"),
+        ];
+        let text = stream.update(&segments, 0, 1600, RATE);
+        assert_eq!(text, "One two. Three four. Five");
+        assert_eq!(stream.prompt(), "One two. Three four.");
+        // The next pass starts OVERLAP_SECS before the finalized audio ends.
+        assert_eq!(stream.window_start(RATE), 1100);
+
+        // It hears the end of "four." again, which is dropped.
+        let segments = [segment(0.0, 0.8, " four."), segment(0.8, 6.0, " Five six")];
+        let text = stream.update(&segments, 1100, 600, RATE);
+        assert_eq!(text, "One two. Three four. Five six");
+
+        let segments = [
+            segment(0.0, 1.5, " four. Five"),
+            segment(1.5, 7.0, " six seven."),
+        ];
+        assert_eq!(
+            stream.finish(&segments, RATE),
+            "One two. Three four. Five six seven."
+        );
+
+        stream.reset();
+        assert_eq!(stream.prompt(), "");
+        assert_eq!(stream.window_start(RATE), 0);
+    }
+
+    #[test]
+    fn test_stereo_source() {
+        use crate::resample::{downmix, resample_to_16khz_mono};
+
+        // Twenty seconds from a stereo microphone, downmixed while capturing.
+        let rate = 16000;
+        let stereo: Vec<f32> = (0..20 * rate * 2)
+            .map(|i| if i % 2 == 0 { 0.5 } else { -0.1 })
+            .collect();
+        let buffer: Vec<f32> = downmix(&stereo, 2).collect();
+        assert_eq!(buffer.len(), 20 * rate as usize);
+
+        // Whisper hears the buffer as is, so its times are buffer times.
+        let audio = resample_to_16khz_mono(&buffer, rate, 1);
+        assert_eq!(audio.len(), buffer.len());
+        let secs = audio.len() as f64 / 16000.0;
+
+        let mut stream = StreamingTranscript::default();
+        let segments = [segment(0.0, 12.0, " One."), segment(12.0, secs, " Two")];
+        assert_eq!(stream.update(&segments, 0, buffer.len(), rate), "One. Two");
+        assert_eq!(stream.window_start(rate), 11 * rate as usize);
+    }
+
+    #[test]
+    fn test_long_segment_is_finalized() {
+        let mut stream = StreamingTranscript::default();
+        let text = stream.update(&[segment(0.0, 26.0, " Long")], 0, 2600, RATE);
+        assert_eq!(text, "Long");
+        assert_eq!(stream.window_start(RATE), 2500);
+    }
+}
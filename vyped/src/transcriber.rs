@@ -1,28 +1,114 @@
 use anyhow::Result;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use vype_shared::Profile;
 use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
 
+/// Transcribed text with its time span, in seconds from the start of the audio.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Probability that the audio holds no speech at all.
+    pub no_speech_prob: f32,
+    /// Mean log probability of the text tokens; closer to 0 is more confident.
+    pub avg_logprob: f32,
+    /// Filled in when [`TranscribeOptions::words`] is set.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Word {
+    pub start: f64,
+    pub end: f64,
+    /// The word with the space before it, if any.
+    pub text: String,
+    /// Mean probability of its tokens.
+    pub probability: f32,
+}
+
+#[derive(Default)]
+pub struct TranscribeOptions {
+    /// Text spoken right before the audio, which keeps Whisper consistent
+    /// across consecutive pieces of audio.
+    pub prompt: String,
+    /// Time each word, at the cost of a slower pass.
+    pub words: bool,
+    /// Setting it stops the pass early with an error.
+    pub abort: Option<Arc<AtomicBool>>,
+}
+
+/// A text token of a segment, with times in seconds.
+struct Token {
+    text: String,
+    start: f64,
+    end: f64,
+    probability: f32,
+}
+
+/// Joins tokens into words; a token starting with a space starts a new word.
+fn words(tokens: &[Token]) -> Vec<Word> {
+    let mut words: Vec<Word> = Vec::new();
+    let mut token_counts: Vec<usize> = Vec::new();
+    for token in tokens {
+        match words.last_mut() {
+            Some(word) if !token.text.starts_with(char::is_whitespace) => {
+                word.text.push_str(&token.text);
+                word.end = token.end;
+                word.probability += token.probability;
+                *token_counts.last_mut().unwrap() += 1;
+            }
+            _ => {
+                words.push(Word {
+                    start: token.start,
+                    end: token.end,
+                    text: token.text.clone(),
+                    probability: token.probability,
+                });
+                token_counts.push(1);
+            }
+        }
+    }
+    for (word, count) in words.iter_mut().zip(token_counts) {
+        word.probability /= count as f32;
+    }
+    words
+}
+
 pub struct WhisperTranscriber {
     ctx: WhisperContext,
-    language: String,
 }
 
 impl WhisperTranscriber {
-    pub fn new(model_path: &str, language: &str) -> Result<Self> {
+    pub fn new(model_path: &str) -> Result<Self> {
         let ctx_params = WhisperContextParameters::default();
         let ctx = WhisperContext::new_with_params(model_path, ctx_params)
             .map_err(|e| anyhow::anyhow!("Failed to load model: {}", e))?;
-        Ok(Self {
-            ctx,
-            language: language.to_string(),
-        })
+        Ok(Self { ctx })
     }
 
-    pub fn transcribe(&self, audio: &[f32]) -> Result<String> {
+    /// Transcribes 16 kHz mono `audio` into segments with their confidence
+    /// and, if asked for, their words.
+    pub fn transcribe_with(
+        &self,
+        audio: &[f32],
+        profile: &Profile,
+        options: TranscribeOptions,
+    ) -> Result<Vec<Segment>> {
         let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
-        params.set_language(Some(&self.language));
-        params.set_translate(false);
+        params.set_language(Some(&profile.language));
+        params.set_translate(profile.translate);
         params.set_no_context(true);
-        params.set_single_segment(true);
+        params.set_token_timestamps(options.words);
+        if !options.prompt.is_empty() {
+            params.set_initial_prompt(&options.prompt);
+        }
+        if let Some(abort) = options.abort {
+            params.set_abort_callback_safe(move || abort.load(Ordering::SeqCst));
+        }
 
         let mut state = self
             .ctx
@@ -33,13 +119,83 @@ impl WhisperTranscriber {
             .full(params, audio)
             .map_err(|e| anyhow::anyhow!("Transcription failed: {}", e))?;
 
-        let mut result = String::new();
+        // Tokens from end-of-text on are special: timestamps, language, task.
+        let eot = self.ctx.token_eot();
+        let mut segments = Vec::new();
         for segment in state.as_iter() {
-            if let Ok(text) = segment.to_str() {
-                result.push_str(text);
+            let Ok(text) = segment.to_str() else {
+                continue;
+            };
+
+            let mut tokens = Vec::new();
+            let mut logprob = 0.0;
+            for i in 0..segment.n_tokens() {
+                let Some(token) = segment.get_token(i) else {
+                    continue;
+                };
+                if token.token_id() >= eot {
+                    continue;
+                }
+                let data = token.token_data();
+                logprob += data.plog;
+                // Whisper timestamps are in centiseconds.
+                tokens.push(Token {
+                    text: token.to_str_lossy()?.into_owned(),
+                    start: data.t0 as f64 / 100.0,
+                    end: data.t1 as f64 / 100.0,
+                    probability: data.p,
+                });
             }
+
+            segments.push(Segment {
+                start: segment.start_timestamp() as f64 / 100.0,
+                end: segment.end_timestamp() as f64 / 100.0,
+                text: text.to_string(),
+                no_speech_prob: segment.no_speech_probability(),
+                avg_logprob: if tokens.is_empty() {
+                    0.0
+                } else {
+                    logprob / tokens.len() as f32
+                },
+                words: if options.words {
+                    words(&tokens)
+                } else {
+                    Vec::new()
+                },
+            });
         }
+        Ok(segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(text: &str, start: f64, end: f64, probability: f32) -> Token {
+        Token {
+            text: text.to_string(),
+            start,
+            end,
+            probability,
+        }
+    }
 
-        Ok(result.trim().to_string())
+    #[test]
+    fn test_words() {
+        let tokens = [
+            token(" Hello", 0.0, 0.4, 0.9),
+            token(" Kub", 0.5, 0.7, 0.5),
+            token("ern", 0.7, 0.8, 0.7),
+            token("etes", 0.8, 1.1, 0.9),
+            token(".", 1.1, 1.2, 1.0),
+        ];
+        let words = words(&tokens);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, " Hello");
+        assert_eq!(words[1].text, " Kubernetes.");
+        assert_eq!((words[1].start, words[1].end), (0.5, 1.2));
+        assert!((words[1].probability - 0.775).abs() < 1e-6);
+        assert!(self::words(&[]).is_empty());
     }
 }
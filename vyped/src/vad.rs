@@ -0,0 +1,230 @@
+use std::ops::Range;
+use vype_shared::VadConfig;
+
+/// Length of the windows the audio level is measured over.
+const FRAME_SECS: f64 = 0.03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    SpeechStarted,
+    SpeechEnded,
+}
+
+/// Energy-based voice activity detector, fed with mono samples as they arrive.
+#[derive(Debug)]
+pub struct VoiceDetector {
+    frame_len: usize,
+    threshold: f32,
+    start_frames: usize,
+    end_frames: usize,
+    /// Samples not yet measured because they do not fill a frame.
+    pending: Vec<f32>,
+    speaking: bool,
+    /// Consecutive frames that disagree with `speaking`.
+    run: usize,
+    /// Samples at the end of the measured audio below the threshold.
+    trailing_silence: usize,
+}
+
+fn frames(secs: f64, frame_len: usize, sample_rate: u32) -> usize {
+    (secs * sample_rate as f64 / frame_len as f64).ceil() as usize
+}
+
+fn rms(frame: &[f32]) -> f32 {
+    (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt()
+}
+
+impl VoiceDetector {
+    pub fn new(config: &VadConfig, sample_rate: u32) -> Self {
+        let mut detector = Self {
+            frame_len: 1,
+            threshold: 0.0,
+            start_frames: 1,
+            end_frames: 1,
+            pending: Vec::new(),
+            speaking: false,
+            run: 0,
+            trailing_silence: 0,
+        };
+        detector.configure(config, sample_rate);
+        detector
+    }
+
+    /// Applies new settings without forgetting whether speech is in progress.
+    pub fn configure(&mut self, config: &VadConfig, sample_rate: u32) {
+        self.frame_len = ((sample_rate as f64 * FRAME_SECS) as usize).max(1);
+        self.threshold = config.threshold;
+        self.start_frames = frames(config.min_speech, self.frame_len, sample_rate).max(1);
+        self.end_frames = frames(config.silence, self.frame_len, sample_rate).max(1);
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.speaking = false;
+        self.run = 0;
+        self.trailing_silence = 0;
+    }
+
+    /// Samples of silence since the last speech, whether or not any speech was heard.
+    pub fn trailing_silence(&self) -> usize {
+        self.trailing_silence
+    }
+
+    /// Number of samples needed to cover `min_speech`, which elapses before
+    /// [`VadEvent::SpeechStarted`] is reported.
+    pub fn start_delay(&self) -> usize {
+        self.start_frames * self.frame_len
+    }
+
+    /// Measures the new samples and reports the first change between speech
+    /// and silence; samples after it are kept for the next call.
+    pub fn feed(&mut self, samples: &[f32]) -> Option<VadEvent> {
+        self.pending.extend_from_slice(samples);
+
+        let mut consumed = 0;
+        let mut event = None;
+        for frame in self.pending.chunks_exact(self.frame_len) {
+            consumed += frame.len();
+            let voiced = rms(frame) >= self.threshold;
+            self.trailing_silence = if voiced {
+                0
+            } else {
+                self.trailing_silence + frame.len()
+            };
+            if voiced == self.speaking {
+                self.run = 0;
+                continue;
+            }
+            self.run += 1;
+            let needed = if self.speaking {
+                self.end_frames
+            } else {
+                self.start_frames
+            };
+            if self.run >= needed {
+                self.speaking = !self.speaking;
+                self.run = 0;
+                event = Some(if self.speaking {
+                    VadEvent::SpeechStarted
+                } else {
+                    VadEvent::SpeechEnded
+                });
+                break;
+            }
+        }
+        self.pending.drain(..consumed);
+        event
+    }
+}
+
+/// The part of a recording without silence at either end, keeping `margin`
+/// samples around the speech. When nothing reaches `threshold` the whole
+/// recording is kept, since a quiet microphone may still have caught speech.
+pub fn trim_silence(
+    samples: &[f32],
+    sample_rate: u32,
+    threshold: f32,
+    margin: usize,
+) -> Range<usize> {
+    let frame_len = ((sample_rate as f64 * FRAME_SECS) as usize).max(1);
+    let voiced = |(_, frame): &(usize, &[f32])| rms(frame) >= threshold;
+
+    let mut frames = samples.chunks(frame_len).enumerate();
+    let Some((first, _)) = frames.clone().find(voiced) else {
+        return 0..samples.len();
+    };
+    let (last, _) = frames.rfind(voiced).unwrap_or((first, &[]));
+
+    let start = (first * frame_len).saturating_sub(margin);
+    let end = ((last + 1) * frame_len + margin).min(samples.len());
+    start..end
+}
+
+/// RMS level of the loudest frame of `samples`, 0 when there are none.
+pub fn peak_level(samples: &[f32], sample_rate: u32) -> f32 {
+    let frame_len = ((sample_rate as f64 * FRAME_SECS) as usize).max(1);
+    samples.chunks(frame_len).map(rms).fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    fn detector() -> VoiceDetector {
+        let config = VadConfig {
+            enabled: true,
+            threshold: 0.1,
+            min_speech: 0.06,
+            pre_roll: 0.0,
+            silence: 0.3,
+        };
+        VoiceDetector::new(&config, RATE)
+    }
+
+    fn tone(secs: f64, level: f32) -> Vec<f32> {
+        (0..(secs * RATE as f64) as usize)
+            .map(|i| if i % 2 == 0 { level } else { -level })
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_speech_and_trailing_silence() {
+        let mut vad = detector();
+
+        assert_eq!(vad.feed(&tone(0.99, 0.01)), None);
+        // A short click is not speech.
+        assert_eq!(vad.feed(&tone(0.03, 0.5)), None);
+        assert_eq!(vad.feed(&tone(0.09, 0.01)), None);
+
+        assert_eq!(vad.feed(&tone(0.5, 0.5)), Some(VadEvent::SpeechStarted));
+        assert_eq!(vad.feed(&[]), None);
+        // Pauses shorter than `silence` keep the utterance going.
+        assert_eq!(vad.feed(&tone(0.2, 0.01)), None);
+        assert_eq!(vad.feed(&tone(0.1, 0.5)), None);
+        assert_eq!(vad.feed(&tone(0.5, 0.01)), Some(VadEvent::SpeechEnded));
+    }
+
+    #[test]
+    fn test_trailing_silence() {
+        let mut vad = detector();
+        vad.feed(&tone(0.3, 0.01));
+        assert_eq!(vad.trailing_silence(), 300);
+        vad.feed(&tone(0.09, 0.5));
+        vad.feed(&tone(0.06, 0.01));
+        assert_eq!(vad.trailing_silence(), 60);
+    }
+
+    #[test]
+    fn test_trim_silence() {
+        let mut audio = tone(0.3, 0.0);
+        audio.extend(tone(0.15, 0.5));
+        audio.extend(tone(0.6, 0.0));
+
+        assert_eq!(trim_silence(&audio, RATE, 0.1, 30), 270..480);
+
+        // Quiet speech below the threshold is not thrown away.
+        assert_eq!(trim_silence(&tone(1.0, 0.01), RATE, 0.1, 30), 0..1000);
+        assert!(trim_silence(&[], RATE, 0.1, 30).is_empty());
+        assert_eq!(trim_silence(&tone(0.1, 0.5), RATE, 0.1, 30).len(), 100);
+    }
+
+    #[test]
+    fn test_peak_level() {
+        let mut audio = tone(0.3, 0.01);
+        audio.extend(tone(0.03, 0.5));
+        assert!((peak_level(&audio, RATE) - 0.5).abs() < 1e-6);
+        assert_eq!(peak_level(&[], RATE), 0.0);
+    }
+
+    #[test]
+    fn test_keeps_samples_after_event() {
+        let mut vad = detector();
+        let mut audio = tone(0.3, 0.5);
+        audio.extend(tone(0.6, 0.0));
+
+        assert_eq!(vad.feed(&audio), Some(VadEvent::SpeechStarted));
+        assert_eq!(vad.feed(&[]), Some(VadEvent::SpeechEnded));
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::Result;
+use log::{debug, error};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
+use vype_shared::{FilterConfig, Profile};
+
+use crate::filter::filter_segments;
+use crate::resample::resample_to_16khz_mono;
+use crate::transcriber::{Segment, TranscribeOptions, WhisperTranscriber, Word};
+use crate::vad::peak_level;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    Partial,
+    Final,
+}
+
+/// Audio of a recording to transcribe.
+pub struct Job {
+    pub kind: JobKind,
+    /// Counts recordings, so results of an earlier one can be told apart.
+    pub recording: u64,
+    /// Samples of the recording the job covers.
+    pub window: Range<usize>,
+    /// Captured mono speech, which starts `offset` seconds into the window.
+    pub audio: Vec<f32>,
+    pub offset: f64,
+    pub sample_rate: u32,
+    pub transcriber: Arc<WhisperTranscriber>,
+    pub profile: Profile,
+    pub prompt: String,
+    pub filter: FilterConfig,
+}
+
+/// A finished job, with segment times relative to the start of its window.
+pub struct Transcription {
+    pub kind: JobKind,
+    pub recording: u64,
+    pub window: Range<usize>,
+    pub segments: Result<Vec<Segment>>,
+    /// Segments dropped as likely hallucinations.
+    pub discarded: Vec<Segment>,
+}
+
+struct Queued {
+    job: Job,
+    abort: Arc<AtomicBool>,
+}
+
+/// Runs transcriptions on a thread of its own so the control loop keeps
+/// handling keys and IPC requests while Whisper works. Jobs run in the order
+/// they were submitted; a partial job that has another job queued behind it
+/// is skipped, since the newer one will hear the same audio and more. A
+/// running partial job is left to finish, or a slow model would never
+/// produce one.
+pub struct TranscriptionWorker {
+    jobs: Sender<Queued>,
+    results: Receiver<Transcription>,
+    /// Abort flag of the last partial job submitted.
+    partial: Option<Arc<AtomicBool>>,
+}
+
+impl TranscriptionWorker {
+    pub fn spawn() -> Result<Self> {
+        let (jobs, job_rx) = channel();
+        let (result_tx, results) = channel();
+        std::thread::Builder::new()
+            .name("transcriber".to_string())
+            .spawn(move || run(job_rx, result_tx))?;
+        Ok(Self {
+            jobs,
+            results,
+            partial: None,
+        })
+    }
+
+    /// Queues `job`. A final job aborts the partial jobs before it, as the
+    /// final transcript replaces whatever they would have typed.
+    pub fn submit(&mut self, job: Job) {
+        if job.kind == JobKind::Final {
+            self.abort_partial();
+        }
+        let abort = Arc::new(AtomicBool::new(false));
+        if job.kind == JobKind::Partial {
+            self.partial = Some(abort.clone());
+        }
+        if self.jobs.send(Queued { job, abort }).is_err() {
+            error!("Transcription worker stopped");
+        }
+    }
+
+    /// Aborts the last partial job submitted if it has not finished yet.
+    pub fn abort_partial(&mut self) {
+        if let Some(abort) = self.partial.take() {
+            abort.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// A finished transcription, if there is one.
+    pub fn poll(&self) -> Option<Transcription> {
+        self.results.try_recv().ok()
+    }
+}
+
+fn run(jobs: Receiver<Queued>, results: Sender<Transcription>) {
+    let mut queue = VecDeque::new();
+    loop {
+        loop {
+            match jobs.try_recv() {
+                Ok(queued) => queue.push_back(queued),
+                Err(TryRecvError::Empty) if queue.is_empty() => match jobs.recv() {
+                    Ok(queued) => queue.push_back(queued),
+                    Err(_) => return,
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        let Some(Queued { job, abort }) = queue.pop_front() else {
+            continue;
+        };
+        let stale = job.kind == JobKind::Partial && !queue.is_empty();
+        if stale || abort.load(Ordering::SeqCst) {
+            debug!("Skipping stale {:?} transcription", job.kind);
+            continue;
+        }
+
+        let segments = transcribe(&job, abort.clone());
+        if abort.load(Ordering::SeqCst) {
+            debug!("Aborted {:?} transcription", job.kind);
+            continue;
+        }
+        let (segments, discarded) = match segments {
+            Ok(segments) => {
+                let level = peak_level(&job.audio, job.sample_rate);
+                let whole = job.window.start == 0;
+                let (kept, discarded) = filter_segments(segments, level, whole, &job.filter);
+                (Ok(kept), discarded)
+            }
+            Err(e) => (Err(e), Vec::new()),
+        };
+        let transcription = Transcription {
+            kind: job.kind,
+            recording: job.recording,
+            window: job.window,
+            segments,
+            discarded,
+        };
+        if results.send(transcription).is_err() {
+            return;
+        }
+    }
+}
+
+fn transcribe(job: &Job, abort: Arc<AtomicBool>) -> Result<Vec<Segment>> {
+    let resampled = resample_to_16khz_mono(&job.audio, job.sample_rate, 1);
+    debug!(
+        "Resampled from {}Hz to 16kHz: {} -> {} samples",
+        job.sample_rate,
+        job.audio.len(),
+        resampled.len()
+    );
+
+    let options = TranscribeOptions {
+        prompt: job.prompt.clone(),
+        abort: Some(abort),
+        ..Default::default()
+    };
+    let segments = job
+        .transcriber
+        .transcribe_with(&resampled, &job.profile, options)?;
+    Ok(segments
+        .into_iter()
+        .map(|segment| Segment {
+            start: segment.start + job.offset,
+            end: segment.end + job.offset,
+            words: segment
+                .words
+                .into_iter()
+                .map(|word| Word {
+                    start: word.start + job.offset,
+                    end: word.end + job.offset,
+                    ..word
+                })
+                .collect(),
+            ..segment
+        })
+        .collect())
+}